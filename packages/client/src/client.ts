@@ -13,10 +13,24 @@ import { cleanNodeDecoding } from '@holochain-open-dev/utils/dist/clean-node-dec
 import {
   Document,
   Commit,
+  CommitAttestation,
+  CommitHistory,
+  CommitHistoryCursor,
+  CommitKind,
+  DirectMessage,
+  ForkSource,
+  JoinWorkspaceSessionOutput,
+  MergeStrategy,
+  RecordWithState,
+  ReplayedSession,
+  ScribeElectionStrategy,
   SendMessageInput,
   SessionMessage,
+  SessionScribe,
   SynSignal,
   Workspace,
+  WorkspaceForkOrigin,
+  WorkspaceRole,
 } from './types.js';
 
 export class SynClient extends ZomeClient<SynSignal> {
@@ -53,6 +67,23 @@ export class SynClient extends ZomeClient<SynSignal> {
     return new EntryRecord(record);
   }
 
+  public async getDocumentWithState(
+    documentHash: AnyDhtHash
+  ): Promise<RecordWithState<Document> | undefined> {
+    const recordWithState:
+      | { record: Record; state: Uint8Array }
+      | undefined = await this.callZome(
+      'get_document_with_state',
+      documentHash
+    );
+    if (!recordWithState) return undefined;
+
+    return {
+      record: new EntryRecord(recordWithState.record),
+      state: recordWithState.state,
+    };
+  }
+
   public async getAuthorsForDocument(
     documentHash: AnyDhtHash
   ): Promise<Array<Link>> {
@@ -121,6 +152,20 @@ export class SynClient extends ZomeClient<SynSignal> {
     return new EntryRecord(record);
   }
 
+  public async getCommitWithState(
+    commitHash: ActionHash
+  ): Promise<RecordWithState<Commit> | undefined> {
+    const recordWithState:
+      | { record: Record; state: Uint8Array }
+      | undefined = await this.callZome('get_commit_with_state', commitHash);
+    if (!recordWithState) return undefined;
+
+    return {
+      record: new EntryRecord(recordWithState.record),
+      state: recordWithState.state,
+    };
+  }
+
   public async getCommitsForDocument(
     documentHash: AnyDhtHash
   ): Promise<Array<Link>> {
@@ -138,6 +183,87 @@ export class SynClient extends ZomeClient<SynSignal> {
     return commits;
   }
 
+  /**
+   * Commits reachable from `commitHash` through their previous commits, newest first,
+   * each one listed before its ancestors. Pass the `next_cursor` of a page to get the next one.
+   */
+  public async getCommitHistory(
+    commitHash: ActionHash,
+    depth?: number,
+    cursor?: CommitHistoryCursor,
+    limit = 20
+  ): Promise<CommitHistory> {
+    return this.callZome('get_commit_history', {
+      commit_hash: commitHash,
+      depth,
+      cursor,
+      limit,
+    });
+  }
+
+  public async findMergeBase(
+    commitA: ActionHash,
+    commitB: ActionHash
+  ): Promise<ActionHash | undefined> {
+    return this.callZome('find_merge_base', {
+      commit_a: commitA,
+      commit_b: commitB,
+    });
+  }
+
+  public async isAncestor(
+    ancestor: ActionHash,
+    descendant: ActionHash
+  ): Promise<boolean> {
+    return this.callZome('is_ancestor', { ancestor, descendant });
+  }
+
+  /**
+   * Signs the commit with the heads of our state, which must be those of the commit's state, and sends the attestation to the commit's author
+   */
+  public async attestCommit(
+    workspaceHash: EntryHash,
+    commitHash: ActionHash,
+    heads: Array<string>
+  ): Promise<Uint8Array> {
+    return this.callZome('attest_commit', {
+      workspace_hash: workspaceHash,
+      commit_hash: commitHash,
+      heads,
+    });
+  }
+
+  /**
+   * Attestations of the commit's witnesses with valid signatures, compare their heads with the commit's state
+   */
+  public async getCommitAttestations(
+    commitHash: ActionHash
+  ): Promise<Array<CommitAttestation>> {
+    return this.callZome('get_commit_attestations', commitHash);
+  }
+
+  /**
+   * The snapshot commit that the given commit's state builds on
+   */
+  public async getLatestSnapshot(
+    commitHash: ActionHash
+  ): Promise<EntryRecord<Commit> | undefined> {
+    const record: Record | undefined = await this.callZome(
+      'get_latest_snapshot',
+      commitHash
+    );
+    return record ? new EntryRecord(record) : undefined;
+  }
+
+  /**
+   * Whether the next commit on top of the given one should be a full snapshot or a delta
+   */
+  public async getNextCommitKind(
+    previousCommitHash: ActionHash
+  ): Promise<CommitKind> {
+    return this.callZome('get_next_commit_kind', previousCommitHash);
+  }
+
   /** Workspaces */
   public async createWorkspace(
     workspace: Workspace,
@@ -150,6 +276,38 @@ export class SynClient extends ZomeClient<SynSignal> {
     return new EntryRecord(record);
   }
 
+  public async forkWorkspace(
+    source: ForkSource,
+    name: string
+  ): Promise<EntryRecord<Workspace>> {
+    const record: Record = await this.callZome('fork_workspace', {
+      source,
+      name,
+    });
+    return new EntryRecord(record);
+  }
+
+  public async getWorkspaceForkOrigin(
+    workspace_hash: EntryHash
+  ): Promise<WorkspaceForkOrigin | undefined> {
+    return this.callZome('get_workspace_fork_origin', workspace_hash);
+  }
+
+  public async mergeWorkspace(
+    source_workspace_hash: EntryHash,
+    target_workspace_hash: EntryHash,
+    strategy: MergeStrategy,
+    meta?: Uint8Array
+  ): Promise<EntryRecord<Commit>> {
+    const record: Record = await this.callZome('merge_workspace', {
+      source_workspace_hash,
+      target_workspace_hash,
+      strategy,
+      meta,
+    });
+    return new EntryRecord(record);
+  }
+
   public async getWorkspace(
     workspace_hash: EntryHash
   ): Promise<EntryRecord<Workspace> | undefined> {
@@ -166,6 +324,42 @@ export class SynClient extends ZomeClient<SynSignal> {
     return this.callZome('get_workspaces_for_document', documentHash);
   }
 
+  public async getWorkspaceByName(
+    documentHash: AnyDhtHash,
+    name: string
+  ): Promise<EntryHash | undefined> {
+    return this.callZome('get_workspace_by_name', {
+      document_hash: documentHash,
+      name,
+    });
+  }
+
+  public async renameWorkspace(
+    workspace_hash: EntryHash,
+    name: string
+  ): Promise<void> {
+    return this.callZome('rename_workspace', { workspace_hash, name });
+  }
+
+  /** Includes archived workspaces, which `getWorkspacesForDocument` leaves out */
+  public async getAllWorkspacesForDocument(
+    documentHash: AnyDhtHash
+  ): Promise<Array<Link>> {
+    return this.callZome('get_all_workspaces_for_document', documentHash);
+  }
+
+  public async archiveWorkspace(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('archive_workspace', workspace_hash);
+  }
+
+  public async unarchiveWorkspace(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('unarchive_workspace', workspace_hash);
+  }
+
+  public async deleteWorkspace(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('delete_workspace', workspace_hash);
+  }
+
   public async getWorkspaceTips(
     workspaceHash: EntryHash
   ): Promise<Array<Link>> {
@@ -184,6 +378,17 @@ export class SynClient extends ZomeClient<SynSignal> {
     });
   }
 
+  /**
+   * Records which tip supersedes the others in a diverged workspace,
+   * the zome picks one deterministically if `winner` is not given
+   */
+  public resolveTips(
+    workspace_hash: EntryHash,
+    winner?: ActionHash
+  ): Promise<ActionHash> {
+    return this.callZome('resolve_tips', { workspace_hash, winner });
+  }
+
   public getWorkspaceSessionParticipants(
     workspace_hash: EntryHash
   ): Promise<Array<Link>> {
@@ -192,14 +397,108 @@ export class SynClient extends ZomeClient<SynSignal> {
 
   public async joinWorkspaceSession(
     workspace_hash: EntryHash
-  ): Promise<AgentPubKey[]> {
+  ): Promise<JoinWorkspaceSessionOutput> {
     return this.callZome('join_workspace_session', workspace_hash);
   }
 
+  /**
+   * The agent's role in the workspace, see `assignWorkspaceRole`
+   */
+  public async getWorkspaceRole(
+    workspace_hash: EntryHash,
+    agent: AgentPubKey
+  ): Promise<WorkspaceRole> {
+    return this.callZome('get_workspace_role', { workspace_hash, agent });
+  }
+
+  /**
+   * Only the owner, the original creator of the workspace, can assign roles. Once any role is assigned,
+   * agents without one are viewers.
+   *
+   * Roles are not enforced by validation: a viewer calling the zome directly can still create
+   * commits and tips. The other session participants ignore the changes and commits of viewers,
+   * and never elect them as scribe.
+   */
+  public async assignWorkspaceRole(
+    workspace_hash: EntryHash,
+    agent: AgentPubKey,
+    role: WorkspaceRole
+  ): Promise<void> {
+    return this.callZome('assign_workspace_role', { workspace_hash, agent, role });
+  }
+
   public async leaveWorkspaceSession(workspace_hash: EntryHash): Promise<void> {
     return this.callZome('leave_workspace_session', workspace_hash);
   }
 
+  /**
+   * Creates a new session key for an encrypted workspace and shares it with the session participants
+   */
+  public async rotateSessionKey(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('rotate_session_key', workspace_hash);
+  }
+
+  /**
+   * Deletes the expired participant links of agents that stopped sending heartbeats, returns how many were deleted
+   */
+  public async removeStaleSessionParticipants(
+    workspace_hash: EntryHash
+  ): Promise<number> {
+    return this.callZome('remove_stale_session_participants', workspace_hash);
+  }
+
+  /**
+   * Starts keeping a private copy of every session message of the workspace sent or received
+   */
+  public async startSessionRecording(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('start_session_recording', workspace_hash);
+  }
+
+  public async stopSessionRecording(workspace_hash: EntryHash): Promise<void> {
+    return this.callZome('stop_session_recording', workspace_hash);
+  }
+
+  public async isRecordingSession(workspace_hash: EntryHash): Promise<boolean> {
+    return this.callZome('is_recording_session', workspace_hash);
+  }
+
+  /**
+   * A page of the recorded session messages of the workspace, oldest first
+   */
+  public async replaySession(
+    workspace_hash: EntryHash,
+    offset = 0,
+    limit = 100
+  ): Promise<ReplayedSession> {
+    return this.callZome('replay_session', { workspace_hash, offset, limit });
+  }
+
+  /**
+   * The scribe of the workspace's session, undefined if there is none or they left
+   */
+  public async getSessionScribe(
+    workspace_hash: EntryHash
+  ): Promise<SessionScribe | undefined> {
+    return this.callZome('get_session_scribe', workspace_hash);
+  }
+
+  /**
+   * Elects a scribe among the session participants, returning the elected agent
+   */
+  public async electScribe(
+    workspace_hash: EntryHash,
+    strategy: ScribeElectionStrategy
+  ): Promise<AgentPubKey> {
+    return this.callZome('elect_scribe', { workspace_hash, strategy });
+  }
+
+  public async handOverScribe(
+    workspace_hash: EntryHash,
+    new_scribe: AgentPubKey
+  ): Promise<void> {
+    return this.callZome('hand_over_scribe', { workspace_hash, new_scribe });
+  }
+
   public sendMessage(
     recipients: Array<AgentPubKey>,
     message: SessionMessage
@@ -209,4 +508,14 @@ export class SynClient extends ZomeClient<SynSignal> {
       message,
     } as SendMessageInput);
   }
+  public sendDirectMessage(
+    recipient: AgentPubKey,
+    message: DirectMessage
+  ): Promise<void> {
+    return this.callZome('send_direct_message', {
+      recipient,
+      message,
+    });
+  }
+
 }
@@ -1,4 +1,4 @@
-import { ActionCommittedSignal } from '@holochain-open-dev/utils';
+import { ActionCommittedSignal, EntryRecord } from '@holochain-open-dev/utils';
 import {
   ActionHash,
   AgentPubKey,
@@ -10,6 +10,9 @@ import {
 export interface Document {
   initial_state: Uint8Array;
   meta: Uint8Array | undefined;
+
+  // Set by the zome when the initial state is too big for a single entry
+  state_chunks?: Array<EntryHash>;
 }
 
 export interface Commit {
@@ -23,22 +26,96 @@ export interface Commit {
   witnesses: Array<AgentPubKey>;
 
   meta: Uint8Array | undefined;
+
+  kind?: CommitKind;
+
+  // Set by the zome when the state is too big for a single entry
+  state_chunks?: Array<EntryHash>;
+}
+
+// Record of a commit or document with its full state, loaded from the chunks if it was split
+export interface RecordWithState<T> {
+  record: EntryRecord<T>;
+  state: Uint8Array;
 }
 
+export type CommitKind =
+  | {
+      type: 'Snapshot';
+    }
+  | {
+      type: 'Delta';
+      base: ActionHash;
+    };
+
 export interface Workspace {
   name: string;
   document_hash: EntryHash;
+  // Session messages carrying document state are end-to-end encrypted
+  encrypted?: boolean;
 }
 
+export type WorkspaceRole = 'Owner' | 'Editor' | 'Viewer';
+
 /** Client API */
 
+export type ForkSource =
+  | {
+      type: 'Workspace';
+      workspace_hash: EntryHash;
+    }
+  | {
+      type: 'Commit';
+      commit_hash: ActionHash;
+    };
+
+export type MergeStrategy =
+  | {
+      type: 'Provided';
+      state: Uint8Array;
+    }
+  | {
+      type: 'Json';
+    };
+
+export interface WorkspaceForkOrigin {
+  commit_hash: ActionHash;
+  source_workspace_hash: EntryHash | undefined;
+}
+
+export interface CommitHistoryEntry {
+  commit_hash: ActionHash;
+  author: AgentPubKey;
+  timestamp: number;
+  commit: Commit;
+}
+
+// Where a page of the commit history left off
+export interface CommitHistoryCursor {
+  frontier: Array<[ActionHash, number]>;
+}
+
+export interface CommitHistory {
+  commits: Array<CommitHistoryEntry>;
+  // Undefined once there is nothing older left to list
+  next_cursor: CommitHistoryCursor | undefined;
+}
+
 export interface SendMessageInput {
   recipients: Array<AgentPubKey>;
   message: SessionMessage;
 }
 
+export interface MessageSequence {
+  // Picked anew every time the sender joins the session, so receivers notice when its counter starts over
+  epoch: number;
+  number: number;
+}
+
 export interface SessionMessage {
   workspace_hash: EntryHash;
+  // Per sender counter for messages that must not get lost
+  sequence?: MessageSequence;
   payload: MessagePayload;
 }
 
@@ -66,8 +143,78 @@ export type MessagePayload =
   | {
       type: 'Heartbeat';
       known_participants: Array<AgentPubKey>;
+      // When the sender sent it, in microseconds
+      sent_at?: number;
+    }
+  | {
+      type: 'TipsDiverged';
+      tips: Array<ActionHash>;
+    }
+  | {
+      type: 'ScribeAnnouncement';
+      term: number;
+    }
+  | {
+      type: 'ScribeHandover';
+      new_scribe: AgentPubKey;
+      term: number;
+    }
+  | {
+      type: 'ScribeElection';
+      scribe: AgentPubKey;
+      term: number;
+    }
+  | {
+      type: 'Ack';
+      sequence: number;
+    }
+  | {
+      type: 'ResendReq';
+      sequences: Array<number>;
+    }
+  | {
+      type: 'App';
+      kind: string;
+      payload: Uint8Array;
+    }
+  | {
+      type: 'SessionKey';
+      sender_key: Uint8Array;
+      encrypted_key: { nonce: Uint8Array; encrypted_data: Uint8Array };
+    }
+  | {
+      type: 'SessionKeyReq';
+    }
+  | {
+      type: 'CommitAttestation';
+      commit_hash: ActionHash;
+      heads: Array<string>;
+      signature: Uint8Array;
     };
 
+export interface SessionScribe {
+  scribe: AgentPubKey;
+  term: number;
+  appointed_at: number;
+}
+
+export type ScribeElectionStrategy =
+  | {
+      type: 'LowestPubKey';
+    }
+  | {
+      // Participants without a measured latency only win if nobody has one
+      type: 'BestLatency';
+      latencies: Array<{ agent: AgentPubKey; latency_ms: number }>;
+    };
+
+export interface CommitAttestation {
+  witness: AgentPubKey;
+  // Heads of the state the witness held
+  heads: Array<string>;
+  signature: Uint8Array;
+}
+
 export type EntryTypes =
   | ({
       type: 'Commit';
@@ -86,10 +233,39 @@ export type LinkTypes =
   | 'WorkspaceToTip'
   | 'WorkspaceToParticipant';
 
+export interface JoinWorkspaceSessionOutput {
+  participants: AgentPubKey[];
+  role: WorkspaceRole;
+}
+
+export interface ReplayedSessionMessage {
+  // The sender, ourselves for the messages we sent
+  provenance: AgentPubKey;
+  recorded_at: number;
+  message: SessionMessage;
+}
+
+export interface ReplayedSession {
+  messages: Array<ReplayedSessionMessage>;
+  total: number;
+  has_more: boolean;
+}
+
+// App specific message to a single agent, outside of any workspace session
+export interface DirectMessage {
+  kind: string;
+  payload: Uint8Array;
+}
+
 export type SynSignal =
   | {
       type: 'SessionMessage';
       provenance: AgentPubKey;
       message: SessionMessage;
     }
+  | {
+      type: 'DirectMessage';
+      provenance: AgentPubKey;
+      message: DirectMessage;
+    }
   | ActionCommittedSignal<EntryTypes, LinkTypes>;
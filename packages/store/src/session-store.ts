@@ -1,4 +1,10 @@
-import { Commit, SessionMessage } from '@holochain-syn/client';
+import {
+  Commit,
+  CommitKind,
+  MessageSequence,
+  SessionMessage,
+  WorkspaceRole,
+} from '@holochain-syn/client';
 import {
   AgentPubKeyMap,
   EntryRecord,
@@ -14,11 +20,12 @@ import {
 } from '@holochain-open-dev/stores';
 import { decode, encode } from '@msgpack/msgpack';
 import Automerge, { FreezeObject } from 'automerge';
-import { encodeHashToBase64, AgentPubKey } from '@holochain/client';
+import { AgentPubKey } from '@holochain/client';
 import isEqual from 'lodash-es/isEqual.js';
 import { toPromise } from '@holochain-open-dev/stores';
 
 import { SynConfig } from './config.js';
+import { loadCommitState } from './syn-store.js';
 import { WorkspaceStore } from './workspace-store.js';
 
 export interface SliceStore<S, E> {
@@ -54,9 +61,29 @@ export function extractSlice<S1, E1, S2, E2>(
 
 export interface SessionParticipant {
   lastSeen: number | undefined;
+  // Delay of their last heartbeat
+  latency?: number;
   syncStates: { state: Automerge.SyncState; ephemeral: Automerge.SyncState };
 }
 
+// How many sent ChangeNotices are kept around for retransmission
+const MAX_UNACKED_MESSAGES = 100;
+
+// Missing messages are requested again this often, until they arrive
+const RESEND_REQUEST_INTERVAL = 2000;
+
+// After this many unanswered requests we fall back to a full sync
+const MAX_RESEND_REQUESTS = 3;
+
+interface ReceivedSequences {
+  epoch: number;
+  highest: number;
+  missing: Set<number>;
+  acked: number;
+  lastResendRequest: number;
+  resendRequests: number;
+}
+
 export class SessionStore<S, E> implements SliceStore<S, E> {
   get workspace() {
     return this.workspaceStore;
@@ -112,6 +139,21 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     return derived(this._currentTip, i => i);
   }
 
+  _scribe: Writable<AgentPubKey | undefined> = writable(undefined);
+  get scribe() {
+    return derived(this._scribe, i => i);
+  }
+
+  // Reliable delivery of our ChangeNotices
+  private epoch = Date.now();
+  private sequence = 0;
+  private unacked: Map<number, SessionMessage> = new Map();
+  private acks: AgentPubKeyMap<number> = new AgentPubKeyMap();
+  private received: AgentPubKeyMap<ReceivedSequences> = new AgentPubKeyMap();
+
+  // Roles are looked up once per participant and session, not on every message
+  private roles: AgentPubKeyMap<Promise<WorkspaceRole>> = new AgentPubKeyMap();
+
   private unsubscribe: () => void = () => { };
   private intervals: any[] = [];
   private deltaCount = 0;
@@ -130,7 +172,9 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     protected config: SynConfig,
     currentState: Automerge.Doc<S>,
     currentTip: EntryRecord<Commit> | undefined,
-    initialParticipants: Array<AgentPubKey>
+    initialParticipants: Array<AgentPubKey>,
+    // The other participants ignore our changes if we are a viewer
+    public readonly role: WorkspaceRole
   ) {
     const workspaceHash = this.workspaceStore.workspaceHash;
     this.unsubscribe = this.synClient.onSignal(async synSignal => {
@@ -162,7 +206,14 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
           });
         }
 
+        // Viewers can follow the session, but we don't take their changes
+        const fromViewer =
+          ['NewCommit', 'ChangeNotice', 'SyncReq'].includes(
+            message.payload.type
+          ) && (await this.isViewer(synSignal.provenance));
+
         if (message.payload.type === 'NewCommit') {
+          if (fromViewer) return;
           const currentTip = get(this._currentTip);
           let newCommit = new EntryRecord<Commit>(message.payload.new_commit);
 
@@ -194,13 +245,28 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
             //     },
             //   }
             // );
+          } else {
+            this.attestCommit(newCommit);
           }
 
           this._currentTip.set(newCommit);
           return;
         }
 
-        if (message.payload.type === 'ChangeNotice') {
+        if (message.sequence !== undefined) {
+          this.handleSequence(synSignal.provenance, message.sequence);
+        }
+        if (message.payload.type === 'Ack') {
+          this.handleAck(synSignal.provenance, message.payload.sequence);
+        }
+        if (message.payload.type === 'ResendReq') {
+          this.handleResendRequest(
+            synSignal.provenance,
+            message.payload.sequences
+          );
+        }
+
+        if (message.payload.type === 'ChangeNotice' && !fromViewer) {
           this.handleChangeNotice(
             synSignal.provenance,
             message.payload.state_changes.map(
@@ -212,6 +278,10 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
           );
         }
         if (message.payload.type === 'SyncReq') {
+          // A full sync covers anything we missed from them
+          const received = this.received.get(synSignal.provenance);
+          if (received) received.missing.clear();
+
           this.handleSyncRequest(
             synSignal.provenance,
             message.payload.sync_message
@@ -223,20 +293,34 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
               ? (decode(
                 message.payload.ephemeral_sync_message
               ) as Automerge.BinarySyncMessage)
-              : undefined
+              : undefined,
+            !fromViewer
           );
         }
 
+        if (message.payload.type === 'ScribeAnnouncement') {
+          this._scribe.set(synSignal.provenance);
+        }
+        if (message.payload.type === 'ScribeHandover') {
+          this._scribe.set(message.payload.new_scribe);
+        }
+
         if (message.payload.type === 'Heartbeat') {
           this.handleHeartbeat(
             synSignal.provenance,
-            message.payload.known_participants
+            message.payload.known_participants,
+            message.payload.sent_at
           );
         }
       }
     });
 
     const discoveryNewParticipants = setInterval(async () => {
+      this.synClient
+        .removeStaleSessionParticipants(workspaceHash)
+        .catch(() => {
+          // Somebody else may be cleaning them up too
+        });
       const participants = await this.synClient.getWorkspaceSessionParticipants(
         workspaceHash
       );
@@ -280,29 +364,24 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
             payload: {
               type: 'Heartbeat',
               known_participants: onlineParticipants,
+              sent_at: Date.now() * 1000,
             },
           });
         }
         return p;
       });
+      this.sendAcks();
+      this.requestMissing();
     }, config.hearbeatInterval);
     this.intervals.push(heartbeatInterval);
 
     const commitInterval = setInterval(async () => {
-      const activeParticipants = get(this.participants)
-        .active.map(p => encodeHashToBase64(p))
-        .sort((p1, p2) => {
-          if (p1 < p2) {
-            return -1;
-          }
-          if (p1 > p2) {
-            return 1;
-          }
-          return 0;
-        });
+      const scribe = get(this._scribe);
 
-      if (activeParticipants[0] === encodeHashToBase64(this.myPubKey)) {
+      if (scribe && isEqual(scribe, this.myPubKey)) {
         this._commitChanges();
+      } else if (!scribe || this.isGone(scribe)) {
+        this.electScribe();
       }
     }, this.config.commitStrategy.CommitEveryNMs);
     this.intervals.push(commitInterval);
@@ -332,6 +411,74 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     for (const p of initialParticipants) {
       this.requestSync(p);
     }
+
+    this.synClient
+      .getSessionScribe(workspaceHash)
+      .then(scribe => this._scribe.set(scribe?.scribe));
+  }
+
+  private isGone(participant: AgentPubKey): boolean {
+    const info = get(this._participants).get(participant);
+    if (!info) return true;
+
+    return (
+      info.lastSeen !== undefined &&
+      Date.now() - info.lastSeen > this.config.outOfSessionTimeout
+    );
+  }
+
+  private roleOf(agent: AgentPubKey): Promise<WorkspaceRole> {
+    if (isEqual(agent, this.myPubKey)) return Promise.resolve(this.role);
+
+    let role = this.roles.get(agent);
+    if (!role) {
+      role = this.synClient.getWorkspaceRole(
+        this.workspaceStore.workspaceHash,
+        agent
+      );
+      // Don't keep a failed lookup around, try again on the next message
+      role.catch(() => this.roles.delete(agent));
+      this.roles.set(agent, role);
+    }
+    return role;
+  }
+
+  private async isViewer(agent: AgentPubKey): Promise<boolean> {
+    return (await this.roleOf(agent)) === 'Viewer';
+  }
+
+  private async electScribe() {
+    // Viewers can't be the scribe, nor elect one
+    if (this.role === 'Viewer') return;
+    const workspaceHash = this.workspaceStore.workspaceHash;
+    const current = await this.synClient.getSessionScribe(workspaceHash);
+
+    if (
+      current &&
+      (isEqual(current.scribe, this.myPubKey) || !this.isGone(current.scribe))
+    ) {
+      this._scribe.set(current.scribe);
+      return;
+    }
+
+    // Whoever we hear from the fastest gets to commit for everyone
+    const participants = Array.from(get(this._participants).entries()).filter(
+      ([agent, info]) => info.latency !== undefined && !this.isGone(agent)
+    );
+    const viewers = await Promise.all(
+      participants.map(([agent]) => this.isViewer(agent))
+    );
+    const latencies = participants
+      .filter((_, i) => !viewers[i])
+      .map(([agent, info]) => ({
+        agent,
+        latency_ms: Math.max(0, Math.round(info.latency!)),
+      }));
+    const scribe = await this.synClient.electScribe(workspaceHash, {
+      type: 'BestLatency',
+      latencies,
+    });
+    this._scribe.set(scribe);
   }
 
   static async joinSession<S, E>(
@@ -339,7 +486,7 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     onLeave: () => void,
     config: SynConfig
   ): Promise<SessionStore<S, E>> {
-    const participants =
+    const { participants, role } =
       await workspaceStore.documentStore.synStore.client.joinWorkspaceSession(
         workspaceStore.workspaceHash
       );
@@ -357,11 +504,15 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
         p =>
           workspaceStore.documentStore.synStore.client.client.myPubKey.toString() !==
           p.toString()
-      )
+      ),
+      role
     );
   }
 
   change(updateFn: (state: S, ephemeral: E) => void) {
+    if (this.role === 'Viewer') {
+      throw new Error('Viewers cannot change the workspace');
+    }
     this._state.update(state => {
       let newState = state;
       this._ephemeral.update(ephemeralState => {
@@ -388,16 +539,22 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
 
         const participants = get(this._participants).keys();
 
+        this.sequence += 1;
+        const message: SessionMessage = {
+          workspace_hash: this.workspaceStore.workspaceHash,
+          sequence: { epoch: this.epoch, number: this.sequence },
+          payload: {
+            type: 'ChangeNotice',
+            state_changes: stateChanges.map(c => encode(c) as any),
+            ephemeral_changes: ephemeralChanges.map(c => encode(c) as any),
+          },
+        };
+        this.unacked.set(this.sequence, message);
+        this.unacked.delete(this.sequence - MAX_UNACKED_MESSAGES);
+
         this.workspaceStore.documentStore.synStore.client.sendMessage(
           Array.from(participants),
-          {
-            workspace_hash: this.workspaceStore.workspaceHash,
-            payload: {
-              type: 'ChangeNotice',
-              state_changes: stateChanges.map(c => encode(c) as any),
-              ephemeral_changes: ephemeralChanges.map(c => encode(c) as any),
-            },
-          }
+          message
         );
         return newEphemeralState;
       });
@@ -441,6 +598,145 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     }
   }
 
+  // Attests to the commit if we hold the same state as the scribe
+  private async attestCommit(commit: EntryRecord<Commit>) {
+    const heads = Automerge.getHeads(get(this._state));
+    try {
+      const commitState = await loadCommitState(this.synClient, commit);
+      if (!isEqual(Automerge.getHeads(commitState).sort(), [...heads].sort()))
+        return;
+
+      await this.synClient.attestCommit(
+        this.workspaceStore.workspaceHash,
+        commit.actionHash,
+        heads
+      );
+    } catch (e) {
+      // The commit is not reachable yet, nothing to attest to
+    }
+  }
+
+  private handleSequence(
+    from: AgentPubKey,
+    { epoch, number: sequence }: MessageSequence
+  ) {
+    const received = this.received.get(from);
+    if (!received || received.epoch !== epoch) {
+      // First message we get from them, nothing to compare against
+      this.received.set(from, {
+        epoch,
+        highest: sequence,
+        missing: new Set(),
+        acked: sequence - 1,
+        lastResendRequest: 0,
+        resendRequests: 0,
+      });
+      // They restarted their session, and may have lost messages we never noticed missing
+      if (received) this.requestSync(from);
+      return;
+    }
+
+    if (sequence <= received.highest) {
+      received.missing.delete(sequence);
+      return;
+    }
+
+    const gap: number[] = [];
+    for (let s = received.highest + 1; s < sequence; s++) {
+      received.missing.add(s);
+      gap.push(s);
+    }
+    received.highest = sequence;
+
+    if (gap.length > 0) {
+      this.sendResendRequest(from, received, gap);
+    }
+  }
+
+  private sendResendRequest(
+    from: AgentPubKey,
+    received: ReceivedSequences,
+    sequences: number[]
+  ) {
+    received.lastResendRequest = Date.now();
+    received.resendRequests += 1;
+    this.synClient.sendMessage([from], {
+      workspace_hash: this.workspaceStore.workspaceHash,
+      payload: {
+        type: 'ResendReq',
+        sequences,
+      },
+    });
+  }
+
+  // Requests again the messages still missing, resend requests and resends can get lost too
+  private requestMissing() {
+    for (const [sender, received] of this.received.entries()) {
+      if (received.missing.size === 0) {
+        received.resendRequests = 0;
+        continue;
+      }
+      if (Date.now() - received.lastResendRequest < RESEND_REQUEST_INTERVAL)
+        continue;
+
+      if (received.resendRequests >= MAX_RESEND_REQUESTS) {
+        received.missing.clear();
+        received.resendRequests = 0;
+        this.requestSync(sender);
+        continue;
+      }
+
+      this.sendResendRequest(sender, received, Array.from(received.missing));
+    }
+  }
+
+  private sendAcks() {
+    for (const [sender, received] of this.received.entries()) {
+      const contiguous =
+        received.missing.size > 0
+          ? Math.min(...received.missing) - 1
+          : received.highest;
+      if (contiguous <= received.acked) continue;
+
+      received.acked = contiguous;
+      this.synClient.sendMessage([sender], {
+        workspace_hash: this.workspaceStore.workspaceHash,
+        payload: {
+          type: 'Ack',
+          sequence: contiguous,
+        },
+      });
+    }
+  }
+
+  private handleAck(from: AgentPubKey, sequence: number) {
+    this.acks.set(from, sequence);
+
+    // Keep what any participant still hasn't acknowledged
+    const acked = Math.min(
+      ...Array.from(get(this._participants).keys()).map(
+        p => this.acks.get(p) ?? 0
+      )
+    );
+    for (const s of this.unacked.keys()) {
+      if (s <= acked) this.unacked.delete(s);
+    }
+  }
+
+  private handleResendRequest(from: AgentPubKey, sequences: number[]) {
+    const messages = sequences.map(s => this.unacked.get(s));
+
+    if (messages.some(m => !m)) {
+      // Already dropped from our buffer: fall back to a full sync
+      this.requestSync(from);
+      return;
+    }
+
+    for (const message of messages) {
+      this.synClient.sendMessage([from], message!);
+    }
+  }
+
   requestSync(participant: AgentPubKey) {
     const syncStates = get(this._participants).get(participant).syncStates;
 
@@ -483,12 +779,14 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
   private handleSyncRequest(
     from: AgentPubKey,
     syncMessage: Automerge.BinarySyncMessage | undefined,
-    ephemeralSyncMessage: Automerge.BinarySyncMessage | undefined
+    ephemeralSyncMessage: Automerge.BinarySyncMessage | undefined,
+    applyState = true
   ) {
     this._participants.update(p => {
       const participantInfo = p.get(from);
 
-      if (syncMessage) {
+      // Viewers still get our state in the reply below, we just don't take theirs
+      if (syncMessage && applyState) {
         this._state.update(state => {
           const [nextDoc, nextSyncState, _message] =
             Automerge.receiveSyncMessage(
@@ -573,6 +871,21 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
 
     const currentTip = get(this._currentTip);
     const previous_commit_hashes = currentTip ? [currentTip.actionHash] : [];
+    const state = get(this._state);
+
+    // Only every so many commits store the whole state, the others the changes since then
+    const kind: CommitKind = currentTip
+      ? await this.synClient.getNextCommitKind(currentTip.actionHash)
+      : { type: 'Snapshot' };
+    let committedState = encode(Automerge.save(state));
+    if (kind.type === 'Delta') {
+      const base = await this.synClient.getCommit(kind.base);
+      const baseState = await loadCommitState(this.synClient, base!);
+      committedState = encode(
+        Automerge.getChanges(baseState as Automerge.Doc<S>, state)
+      );
+    }
+
     const commit: Commit = {
       authors: [
         ...Array.from(get(this._participants).keys()),
@@ -580,9 +893,10 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
       ],
       meta,
       previous_commit_hashes,
-      state: encode(Automerge.save(get(this._state))),
+      state: committedState,
       witnesses: [],
       document_hash: this.workspaceStore.documentStore.documentHash,
+      kind,
     };
 
     const newCommit = await this.synClient.createCommit(commit);
@@ -606,8 +920,17 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
     );
   }
 
-  private handleHeartbeat(_from: AgentPubKey, participants: AgentPubKey[]) {
+  private handleHeartbeat(
+    from: AgentPubKey,
+    participants: AgentPubKey[],
+    sentAt: number | undefined
+  ) {
     this._participants.update(p => {
+      const info = p.get(from);
+      if (info && sentAt !== undefined) {
+        info.latency = Date.now() - sentAt / 1000;
+      }
+
       const newParticipants = participants.filter(
         maybeNew => !p.has(maybeNew) && !isEqual(maybeNew, this.myPubKey)
       );
@@ -646,6 +969,8 @@ export class SessionStore<S, E> implements SliceStore<S, E> {
   }
 
   private handleNewParticipant(participant: AgentPubKey) {
+    // Resolve their role once, before their changes arrive
+    this.roleOf(participant).catch(() => {});
     this._participants.update(p => {
       p.set(participant, {
         lastSeen: Date.now(),
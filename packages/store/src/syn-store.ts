@@ -7,11 +7,17 @@ import {
 import { Commit, Document, SynClient } from '@holochain-syn/client';
 import { decode, encode } from '@msgpack/msgpack';
 import Automerge from 'automerge';
-import { LazyHoloHashMap, LazyMap, slice } from '@holochain-open-dev/utils';
+import {
+  EntryRecord,
+  LazyHoloHashMap,
+  LazyMap,
+  slice,
+} from '@holochain-open-dev/utils';
 import { AnyDhtHash, EntryHash } from '@holochain/client';
 
 import { DocumentStore } from './document-store.js';
 
+// Full state of a snapshot commit, delta commits need `loadCommitState`
 export const stateFromCommit = (commit: Commit) => {
   const commitState = decode(commit.state) as Automerge.BinaryDocument;
   const state = Automerge.load(commitState);
@@ -26,6 +32,49 @@ export const stateFromDocument = (document: Document) => {
   return state;
 };
 
+const loadCommitBytes = async (
+  client: SynClient,
+  commit: EntryRecord<Commit>
+) => {
+  if (!commit.entry.state_chunks?.length) return decode(commit.entry.state);
+
+  const commitWithState = await client.getCommitWithState(commit.actionHash);
+  return decode(commitWithState!.state);
+};
+
+export const loadCommitState = async (
+  client: SynClient,
+  commit: EntryRecord<Commit>
+) => {
+  const kind = commit.entry.kind;
+  if (kind?.type !== 'Delta')
+    return Automerge.load(
+      (await loadCommitBytes(client, commit)) as Automerge.BinaryDocument
+    );
+
+  // A delta only holds the changes made on top of its base snapshot
+  const base = await client.getCommit(kind.base);
+  if (!base) throw new Error('Could not find the base snapshot of the commit');
+  const [state] = Automerge.applyChanges(
+    await loadCommitState(client, base),
+    (await loadCommitBytes(client, commit)) as Automerge.BinaryChange[]
+  );
+  return state;
+};
+
+export const loadDocumentState = async (
+  client: SynClient,
+  document: EntryRecord<Document>
+) => {
+  if (!document.entry.state_chunks?.length)
+    return stateFromDocument(document.entry);
+
+  const documentWithState = await client.getDocumentWithState(
+    document.entryHash
+  );
+  return Automerge.load(decode(documentWithState!.state) as Automerge.BinaryDocument);
+};
+
 export class SynStore {
   /** Public accessors */
 
@@ -26,7 +26,7 @@ import Automerge from 'automerge';
 import { defaultConfig, RecursivePartial, SynConfig } from './config.js';
 import { DocumentStore } from './document-store.js';
 import { SessionStore } from './session-store.js';
-import { stateFromCommit, stateFromDocument } from './syn-store.js';
+import { loadCommitState, loadDocumentState } from './syn-store.js';
 
 export class WorkspaceStore<S, E> {
   constructor(
@@ -65,21 +65,15 @@ export class WorkspaceStore<S, E> {
     const commits = await toPromise(
       sliceAndJoin(this.documentStore.commits, commitsHashes)
     );
-    // If there are more that one tip, merge them
-    let mergeState: Automerge.Doc<S> = Automerge.merge(
-      Automerge.load(
-        decode(Array.from(commits.values())[0].entry.state) as any
-      ),
-      Automerge.load(decode(Array.from(commits.values())[1].entry.state) as any)
+    const client = this.documentStore.synStore.client;
+    const states = await Promise.all(
+      Array.from(commits.values()).map(commit => loadCommitState(client, commit))
     );
+    // If there are more that one tip, merge them
+    let mergeState: Automerge.Doc<S> = Automerge.merge(states[0], states[1]);
 
-    for (let i = 2; i < commitsHashes.length; i++) {
-      mergeState = Automerge.merge(
-        mergeState,
-        Automerge.load(
-          decode(Array.from(commits.values())[i].entry.state) as any
-        )
-      );
+    for (let i = 2; i < states.length; i++) {
+      mergeState = Automerge.merge(mergeState, states[i]);
     }
 
     const documentHash = this.documentStore.documentHash;
@@ -167,10 +161,17 @@ export class WorkspaceStore<S, E> {
    */
   latestSnapshot: AsyncReadable<S> = pipe(this.tip, commit =>
     commit
-      ? (stateFromCommit(commit.entry) as S)
+      ? (loadCommitState(
+        this.documentStore.synStore.client,
+        commit
+      ) as Promise<S>)
       : pipe(
         this.documentStore.record,
-        document => stateFromDocument(document.entry) as S
+        document =>
+          loadDocumentState(
+            this.documentStore.synStore.client,
+            document
+          ) as Promise<S>
       )
   );
 
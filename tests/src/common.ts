@@ -1,4 +1,13 @@
-import { AgentPubKey } from '@holochain/client';
+import { readFileSync } from 'fs';
+import { gunzipSync } from 'zlib';
+import {
+  ActionHash,
+  AgentPubKey,
+  AppBundle,
+  encodeHashToBase64,
+} from '@holochain/client';
+import { enableAndGetAgentApp, Player, Scenario } from '@holochain/tryorama';
+import { decode } from '@msgpack/msgpack';
 import { SessionStore } from '@holochain-syn/store';
 
 import {
@@ -29,6 +38,111 @@ export type Signal = {
 
 export const delay = (ms: number) => new Promise(r => setTimeout(r, ms));
 
+/*
+  The test happ with the given DNA properties
+*/
+export function synBundle(properties: object): AppBundle {
+  const bundle = decode(gunzipSync(readFileSync(synHapp))) as AppBundle;
+  (bundle.manifest.roles[0].dna as any).properties = properties;
+  return bundle;
+}
+
+/*
+  Installs the test happ for a new agent listed as store agent in the DNA properties.
+  Other players install the returned bundle to join the same network.
+*/
+export async function addStoreAgent(scenario: Scenario) {
+  const conductor = await scenario.addConductor();
+  const agentPubKey = await conductor.adminWs().generateAgentPubKey();
+
+  const bundle = synBundle({
+    store_agents: [encodeHashToBase64(agentPubKey)],
+  });
+
+  const appInfo = await conductor.installApp({ bundle }, { agentPubKey });
+  const port = await conductor.attachAppInterface();
+  const appWs = await conductor.connectAppAgentWs(
+    port,
+    appInfo.installed_app_id
+  );
+  const agentApp = await enableAndGetAgentApp(
+    conductor.adminWs(),
+    appWs,
+    appInfo
+  );
+
+  return { player: { conductor, appWs, ...agentApp }, bundle };
+}
+
+export function callSyn<T = any>(
+  player: Player,
+  fn_name: string,
+  payload: any = null
+): Promise<T> {
+  return player.cells[0].callZome({ zome_name: 'syn', fn_name, payload });
+}
+
+/*
+  Publishes a product in the catalog, returning its action hash
+*/
+export async function createProduct(
+  player: Player,
+  name: string,
+  price: number,
+  category = 'Pantry',
+  subcategory = 'Baking',
+  stocks_status = 'Many in stock'
+): Promise<ActionHash> {
+  const record: any = await player.cells[0].callZome({
+    zome_name: 'products',
+    fn_name: 'create_product',
+    payload: {
+      product: {
+        name,
+        price,
+        size: '1 unit',
+        stocks_status,
+        category,
+        subcategory,
+        product_type: 'Flour',
+        image_url: undefined,
+      },
+      main_category: category,
+      subcategory,
+      product_type: 'Flour',
+    },
+  });
+  return record.signed_action.hashed.hash;
+}
+
+/*
+  Clones the cart DNA and creates the cart entry for it like the UI does, returning the cart id
+*/
+export async function createCart(player: Player): Promise<string> {
+  const document: any = await callSyn(player, 'create_document', {
+    initial_state: new Uint8Array(),
+    meta: undefined,
+  });
+  const documentHash = document.signed_action.hashed.content.entry_hash;
+  const cloneInfo: any = await callSyn(player, 'clone_cart_dna', {
+    document_hash: documentHash,
+    cart_name: 'Cart',
+    created_at: Date.now() * 1000,
+  });
+  await callSyn(player, 'create_cart_entry', {
+    input: {
+      document_hash: documentHash,
+      cart_name: 'Cart',
+      created_at: cloneInfo.created_at,
+    },
+    created_at: cloneInfo.created_at,
+    cart_dna_hash: cloneInfo.cart_dna_hash,
+  });
+  return `Cart_${encodeHashToBase64(cloneInfo.cart_dna_hash)}_${
+    cloneInfo.created_at
+  }`;
+}
+
 /*
   Fake UI functions
     - applyDeltas
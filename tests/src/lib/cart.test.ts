@@ -0,0 +1,221 @@
+import { assert, test } from 'vitest';
+
+import { dhtSync, runScenario } from '@holochain/tryorama';
+import { encodeHashToBase64 } from '@holochain/client';
+import { decode } from '@msgpack/msgpack';
+
+import { callSyn, createCart, createProduct, synHapp } from '../common.js';
+
+test('spend analytics use the order of each processed cart', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+
+    const flour = await createProduct(alice, 'Flour', 2.5);
+    const cartId = await createCart(alice);
+    await callSyn(alice, 'set_cart_items', {
+      cart_id: cartId,
+      items: [{ product_hash: flour, quantity: 3 }],
+    });
+
+    // Active and checked out carts are not spending yet
+    let analytics: any = await callSyn(alice, 'get_spend_analytics');
+    assert.equal(analytics.cart_count, 0);
+
+    const order: any = await callSyn(alice, 'checkout_cart', cartId);
+    await callSyn(alice, 'update_cart_status', {
+      cart_id: cartId,
+      status: 'Processed',
+    });
+
+    analytics = await callSyn(alice, 'get_spend_analytics');
+    assert.equal(analytics.cart_count, 1);
+    assert.equal(analytics.item_count, 3);
+    assert.equal(analytics.total, 7.5);
+    assert.equal(analytics.buckets.length, 1);
+    assert.equal(analytics.buckets[0].category, 'Pantry');
+    assert.equal(analytics.buckets[0].subcategory, 'Baking');
+    assert.equal(analytics.buckets[0].total, 7.5);
+
+    // Bucketed by the month of the checkout
+    const month = new Date(
+      order.signed_action.hashed.content.timestamp / 1000
+    )
+      .toISOString()
+      .slice(0, 7);
+    assert.equal(analytics.buckets[0].month, month);
+  });
+});
+
+test('exported carts are imported as new carts in their own cell', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+
+    const flour = await createProduct(alice, 'Flour, wholegrain', 2.5);
+    const cartId = await createCart(alice);
+    await callSyn(alice, 'set_cart_items', {
+      cart_id: cartId,
+      items: [{ product_hash: flour, quantity: 2 }],
+    });
+
+    const exported: any = await callSyn(alice, 'export_cart', cartId);
+    const document = JSON.parse(exported.json);
+    assert.equal(document.version, 1);
+    assert.equal(document.items.length, 1);
+    assert.equal(document.total, 5);
+    assert.deepEqual(exported.csv.trim().split('\n'), [
+      'product name,size,unit price,quantity,line total',
+      '"Flour, wholegrain",1 unit,2.50,2,5.00',
+    ]);
+
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    const record: any = await callSyn(bob, 'import_cart', exported.json);
+    const imported: any = decode(record.entry.Present.entry);
+    assert.equal(imported.status, 'Active');
+    assert.equal(imported.items.length, 1);
+    assert.equal(imported.items[0].quantity, 2);
+    assert.notEqual(imported.cart_name, document.cart_name);
+    assert.notEqual(
+      encodeHashToBase64(imported.cart_dna_hash),
+      document.cart_dna_hash
+    );
+
+    const importedId = `Cart_${encodeHashToBase64(imported.cart_dna_hash)}_${
+      imported.created_at
+    }`;
+    const cellId: any = await callSyn(
+      bob,
+      'get_cell_for_cart',
+      imported.cart_name
+    );
+    assert.deepEqual(cellId[0], imported.cart_dna_hash);
+    assert.deepEqual(cellId[1], bob.agentPubKey);
+
+    // Bob's clone is registered, so the imported cart goes through the usual lifecycle
+    await callSyn(bob, 'update_cart_status', {
+      cart_id: importedId,
+      status: 'CheckedOut',
+    });
+    const carts: Array<any> = await callSyn(bob, 'get_all_carts');
+    assert.equal(carts.length, 0);
+  });
+});
+
+test('the bill split is computed from assignments and recorded in the order', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+
+    const flour = await createProduct(alice, 'Flour', 2);
+    const sugar = await createProduct(alice, 'Sugar', 3);
+    const cartId = await createCart(alice);
+    await callSyn(alice, 'set_cart_items', {
+      cart_id: cartId,
+      items: [
+        { product_hash: flour, quantity: 2 },
+        { product_hash: sugar, quantity: 1 },
+      ],
+    });
+    await callSyn(alice, 'assign_cart_item', {
+      cart_id: cartId,
+      product_hash: flour,
+      shares: [
+        { agent: alice.agentPubKey, ratio: 1 },
+        { agent: bob.agentPubKey, ratio: 3 },
+      ],
+    });
+
+    const shareOf = (split: any, agent: any) =>
+      split.shares.find(
+        (share: any) =>
+          encodeHashToBase64(share.agent) === encodeHashToBase64(agent)
+      )?.amount;
+
+    const split: any = await callSyn(alice, 'get_bill_split', cartId);
+    assert.equal(split.total, 7);
+    // A quarter of the flour plus all of the unassigned sugar
+    assert.equal(shareOf(split, alice.agentPubKey), 4);
+    assert.equal(shareOf(split, bob.agentPubKey), 3);
+
+    const record: any = await callSyn(alice, 'checkout_cart', cartId);
+    const order: any = decode(record.entry.Present.entry);
+    assert.equal(order.total, 7);
+    assert.equal(shareOf({ shares: order.bill_split }, bob.agentPubKey), 3);
+
+    // Checked out carts can't be reassigned
+    let reassigned = true;
+    try {
+      await callSyn(alice, 'assign_cart_item', {
+        cart_id: cartId,
+        product_hash: sugar,
+        shares: [{ agent: bob.agentPubKey, ratio: 1 }],
+      });
+    } catch (e) {
+      reassigned = false;
+    }
+    assert.notOk(reassigned);
+  });
+});
+
+test('checkout proposes available substitutes as each item allows', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+
+    const flour = await createProduct(
+      alice,
+      'Flour',
+      2,
+      'Pantry',
+      'Baking',
+      'Out of stock'
+    );
+    const spelt = await createProduct(alice, 'Spelt flour', 3);
+    await createProduct(alice, 'Rye flour', 3, 'Pantry', 'Baking', 'Sold out');
+    const yeast = await createProduct(
+      alice,
+      'Yeast',
+      1,
+      'Pantry',
+      'Baking',
+      'Out of stock'
+    );
+
+    const cartId = await createCart(alice);
+    await callSyn(alice, 'set_cart_items', {
+      cart_id: cartId,
+      items: [
+        { product_hash: flour, quantity: 1 },
+        { product_hash: yeast, quantity: 1 },
+      ],
+    });
+    await callSyn(alice, 'set_item_substitution', {
+      cart_id: cartId,
+      product_hash: flour,
+      substitution: { type: 'AnySameType' },
+    });
+
+    const record: any = await callSyn(alice, 'checkout_cart', cartId);
+    const order: any = decode(record.entry.Present.entry);
+    const substitutesFor = (product: any) =>
+      order.substitutions
+        .find(
+          (proposal: any) =>
+            encodeHashToBase64(proposal.product_hash) ===
+            encodeHashToBase64(product)
+        )
+        .substitutes.map(encodeHashToBase64);
+
+    // Yeast keeps the default policy, which allows no substitutes
+    assert.deepEqual(substitutesFor(flour), [encodeHashToBase64(spelt)]);
+    assert.deepEqual(substitutesFor(yeast), []);
+  });
+});
@@ -1,6 +1,7 @@
 import { assert, test } from 'vitest';
 
 import { dhtSync, pause, runScenario } from '@holochain/tryorama';
+import { ActionHash, AnyDhtHash } from '@holochain/client';
 
 import { get } from '@holochain-open-dev/stores';
 import { SynStore } from '@holochain-syn/store';
@@ -138,3 +139,334 @@ test('check that the state of disconnected agents making changes converges after
     await alice.conductor.shutDown();
   });
 });
+
+test('merging a workspace three-way merges its JSON state into the target', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const client = new SynClient(alice.appWs as any, 'syn-test');
+
+    const jsonState = (state: any) =>
+      new TextEncoder().encode(JSON.stringify(state));
+    const commitJson = async (state: any, previous: ActionHash[]) =>
+      client.createCommit({
+        state: jsonState(state),
+        document_hash: document.entryHash,
+        previous_commit_hashes: previous,
+        authors: [alice.agentPubKey],
+        witnesses: [],
+        meta: undefined,
+      });
+
+    const document = await client.createDocument({
+      initial_state: jsonState({ a: 1, b: 1 }),
+      meta: undefined,
+    });
+    const root = await commitJson({ a: 1, b: 1 }, []);
+
+    const main = await client.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      root.actionHash
+    );
+    const feature = await client.forkWorkspace(
+      { type: 'Workspace', workspace_hash: main.entryHash },
+      'feature'
+    );
+
+    const featureCommit = await commitJson({ a: 1, b: 2 }, [root.actionHash]);
+    await client.updateWorkspaceTip(feature.entryHash, featureCommit.actionHash, [
+      root.actionHash,
+    ]);
+    const mainCommit = await commitJson({ a: 3, b: 1 }, [root.actionHash]);
+    await client.updateWorkspaceTip(main.entryHash, mainCommit.actionHash, [
+      root.actionHash,
+    ]);
+
+    const merge = await client.mergeWorkspace(
+      feature.entryHash,
+      main.entryHash,
+      { type: 'Json' }
+    );
+
+    assert.deepEqual(
+      JSON.parse(new TextDecoder().decode(merge.entry.state)),
+      { a: 3, b: 2 }
+    );
+    assert.deepEqual(
+      merge.entry.previous_commit_hashes.map(h => h.toString()),
+      [mainCommit.actionHash.toString(), featureCommit.actionHash.toString()]
+    );
+
+    const mainTips = await client.getWorkspaceTips(main.entryHash);
+    assert.equal(mainTips.length, 1);
+    assert.equal(mainTips[0].target.toString(), merge.actionHash.toString());
+
+    // Merging again is a no-op, the target already contains the source
+    const again = await client.mergeWorkspace(
+      feature.entryHash,
+      main.entryHash,
+      { type: 'Json' }
+    );
+    assert.equal(again.actionHash.toString(), merge.actionHash.toString());
+  });
+});
+
+test('workspace tips only accept commits of the document and resolve to one of the tips', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const client = new SynClient(alice.appWs as any, 'syn-test');
+
+    const createCommit = async (
+      documentHash: AnyDhtHash,
+      previous: ActionHash[]
+    ) =>
+      client.createCommit({
+        state: new TextEncoder().encode(JSON.stringify({})),
+        document_hash: documentHash,
+        previous_commit_hashes: previous,
+        authors: [alice.agentPubKey],
+        witnesses: [],
+        meta: undefined,
+      });
+
+    const document = await client.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const otherDocument = await client.createDocument({
+      initial_state: new TextEncoder().encode('{"other":true}'),
+      meta: undefined,
+    });
+
+    const root = await createCommit(document.entryHash, []);
+    const workspace = await client.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      root.actionHash
+    );
+
+    // A commit of another document can't become a tip
+    const foreign = await createCommit(otherDocument.entryHash, []);
+    let accepted = true;
+    try {
+      await client.updateWorkspaceTip(workspace.entryHash, foreign.actionHash, [
+        root.actionHash,
+      ]);
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+
+    // Two concurrent commits diverge the workspace
+    const left = await createCommit(document.entryHash, [root.actionHash]);
+    const right = await createCommit(document.entryHash, [root.actionHash]);
+    await client.updateWorkspaceTip(workspace.entryHash, left.actionHash, [
+      root.actionHash,
+    ]);
+    await client.updateWorkspaceTip(workspace.entryHash, right.actionHash, [
+      root.actionHash,
+    ]);
+    let tips = await client.getWorkspaceTips(workspace.entryHash);
+    assert.equal(tips.length, 2);
+
+    // Only one of the tips can win
+    accepted = true;
+    try {
+      await client.resolveTips(workspace.entryHash, foreign.actionHash);
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+
+    const winner = await client.resolveTips(
+      workspace.entryHash,
+      left.actionHash
+    );
+    assert.equal(winner.toString(), left.actionHash.toString());
+
+    tips = await client.getWorkspaceTips(workspace.entryHash);
+    assert.equal(tips.length, 1);
+    assert.equal(tips[0].target.toString(), left.actionHash.toString());
+  });
+});
+
+test('merge bases and ancestry follow the commit DAG', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const client = new SynClient(alice.appWs as any, 'syn-test');
+
+    const document = await client.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    let count = 0;
+    const commit = async (previous: ActionHash[]) =>
+      (
+        await client.createCommit({
+          state: new TextEncoder().encode(JSON.stringify({ count: count++ })),
+          document_hash: document.entryHash,
+          previous_commit_hashes: previous,
+          authors: [alice.agentPubKey],
+          witnesses: [],
+          meta: undefined,
+        })
+      ).actionHash;
+
+    //   root - a - b - merge
+    //      \          /
+    //       c -------
+    const root = await commit([]);
+    const a = await commit([root]);
+    const b = await commit([a]);
+    const c = await commit([root]);
+    const merge = await commit([b, c]);
+
+    assert.equal((await client.findMergeBase(b, c))!.toString(), root.toString());
+    assert.equal((await client.findMergeBase(merge, c))!.toString(), c.toString());
+    assert.equal((await client.findMergeBase(a, b))!.toString(), a.toString());
+
+    assert.ok(await client.isAncestor(root, merge));
+    assert.ok(await client.isAncestor(c, merge));
+    assert.ok(await client.isAncestor(b, b));
+    assert.notOk(await client.isAncestor(c, b));
+    assert.notOk(await client.isAncestor(merge, root));
+
+    // Unrelated roots share no history
+    const otherRoot = await commit([]);
+    assert.notOk(await client.findMergeBase(b, otherRoot));
+  });
+});
+
+test('forks branch off a workspace tip or any commit', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const client = new SynClient(alice.appWs as any, 'syn-test');
+
+    const document = await client.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const commit = async (state: string, previous: ActionHash[]) =>
+      (
+        await client.createCommit({
+          state: new TextEncoder().encode(state),
+          document_hash: document.entryHash,
+          previous_commit_hashes: previous,
+          authors: [alice.agentPubKey],
+          witnesses: [],
+          meta: undefined,
+        })
+      ).actionHash;
+
+    const root = await commit('root', []);
+    const main = await client.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      root
+    );
+    const tip = await commit('tip', [root]);
+    await client.updateWorkspaceTip(main.entryHash, tip, [root]);
+
+    const feature = await client.forkWorkspace(
+      { type: 'Workspace', workspace_hash: main.entryHash },
+      'feature'
+    );
+    let tips = await client.getWorkspaceTips(feature.entryHash);
+    assert.deepEqual(
+      tips.map(t => t.target.toString()),
+      [tip.toString()]
+    );
+    let origin = await client.getWorkspaceForkOrigin(feature.entryHash);
+    assert.equal(origin!.commit_hash.toString(), tip.toString());
+    assert.equal(
+      origin!.source_workspace_hash!.toString(),
+      main.entryHash.toString()
+    );
+
+    const hotfix = await client.forkWorkspace(
+      { type: 'Commit', commit_hash: root },
+      'hotfix'
+    );
+    tips = await client.getWorkspaceTips(hotfix.entryHash);
+    assert.deepEqual(
+      tips.map(t => t.target.toString()),
+      [root.toString()]
+    );
+    origin = await client.getWorkspaceForkOrigin(hotfix.entryHash);
+    assert.equal(origin!.commit_hash.toString(), root.toString());
+    assert.notOk(origin!.source_workspace_hash);
+
+    // Workspaces created from scratch have no fork origin, and names stay unique
+    assert.notOk(await client.getWorkspaceForkOrigin(main.entryHash));
+    let duplicated = true;
+    try {
+      await client.forkWorkspace(
+        { type: 'Commit', commit_hash: tip },
+        'feature'
+      );
+    } catch (e) {
+      duplicated = false;
+    }
+    assert.notOk(duplicated);
+  });
+});
+
+test('a new tip only replaces its own previous commits', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const client = new SynClient(alice.appWs as any, 'syn-test');
+
+    const document = await client.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    let count = 0;
+    const commit = async (previous: ActionHash[]) =>
+      (
+        await client.createCommit({
+          state: new TextEncoder().encode(JSON.stringify({ count: count++ })),
+          document_hash: document.entryHash,
+          previous_commit_hashes: previous,
+          authors: [alice.agentPubKey],
+          witnesses: [],
+          meta: undefined,
+        })
+      ).actionHash;
+    const rejected = async (tip: ActionHash, previous: ActionHash[]) => {
+      try {
+        await client.updateWorkspaceTip(workspace.entryHash, tip, previous);
+      } catch (e) {
+        return true;
+      }
+      return false;
+    };
+
+    const root = await commit([]);
+    const workspace = await client.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      root
+    );
+    const side = await commit([root]);
+    const a = await commit([root]);
+    const b = await commit([a]);
+
+    // Neither an unrelated commit nor one further back can be replaced by b
+    assert.ok(await rejected(b, [side]));
+    assert.ok(await rejected(b, [root]));
+
+    assert.notOk(await rejected(a, [root]));
+    assert.notOk(await rejected(b, [a]));
+    const tips = await client.getWorkspaceTips(workspace.entryHash);
+    assert.deepEqual(
+      tips.map(t => t.target.toString()),
+      [b.toString()]
+    );
+  });
+});
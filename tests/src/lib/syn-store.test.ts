@@ -1,16 +1,26 @@
 import { assert, test } from 'vitest';
 
 import { dhtSync, runScenario } from '@holochain/tryorama';
+import { encodeHashToBase64 } from '@holochain/client';
+import { encode } from '@msgpack/msgpack';
 import { get, toPromise } from '@holochain-open-dev/stores';
 
-import { SynStore, stateFromCommit } from '@holochain-syn/store';
+import {
+  SynStore,
+  loadCommitState,
+  stateFromCommit,
+} from '@holochain-syn/store';
 import { SynClient } from '@holochain-syn/client';
 
 import { textEditorGrammar } from '../text-editor-grammar.js';
 import {
+  addStoreAgent,
+  callSyn,
+  createCart,
   waitForOtherParticipants,
   delay,
   sampleGrammar,
+  synBundle,
   synHapp,
 } from '../common.js';
 
@@ -200,3 +210,1233 @@ test('SynStore, DocumentStore, WorkspaceStore and SessionStore work', async () =
     assert.deepEqual(latestState, state);
   });
 });
+
+test('delivery slots are never reserved beyond their capacity', async () => {
+  await runScenario(async scenario => {
+    const { player: store, bundle } = await addStoreAgent(scenario);
+    const [bob, carol] = await scenario.addPlayersWithApps([
+      { appBundleSource: { bundle } },
+      { appBundleSource: { bundle } },
+    ]);
+    await scenario.shareAllAgents();
+
+    const now = Date.now() * 1000;
+    const slot: any = await store.cells[0].callZome({
+      zome_name: 'syn',
+      fn_name: 'create_delivery_slot',
+      payload: {
+        kind: 'Delivery',
+        location: 'Main street',
+        starts_at: now + 3600 * 1000 * 1000,
+        ends_at: now + 2 * 3600 * 1000 * 1000,
+        capacity: 1,
+      },
+    });
+    const slotHash = slot.signed_action.hashed.hash;
+
+    const createCart = async (player: typeof bob) => {
+      const syn = new SynClient(player.appWs as any, 'syn-test');
+      const document = await syn.createDocument({
+        initial_state: new Uint8Array(),
+        meta: undefined,
+      });
+      const createdAt = Date.now() * 1000;
+      const cartDnaHash = player.cells[0].cell_id[0];
+      await player.cells[0].callZome({
+        zome_name: 'syn',
+        fn_name: 'create_cart_entry',
+        payload: {
+          input: {
+            document_hash: document.entryHash,
+            cart_name: 'Cart',
+            created_at: createdAt,
+          },
+          created_at: createdAt,
+          cart_dna_hash: cartDnaHash,
+        },
+      });
+      return `Cart_${encodeHashToBase64(cartDnaHash)}_${createdAt}`;
+    };
+    const bobCartId = await createCart(bob);
+    const carolCartId = await createCart(carol);
+
+    await dhtSync([store, bob, carol], bob.cells[0].cell_id[0]);
+
+    await bob.cells[0].callZome({
+      zome_name: 'syn',
+      fn_name: 'reserve_delivery_slot',
+      payload: { cart_id: bobCartId, slot_hash: slotHash },
+    });
+
+    await dhtSync([store, bob, carol], bob.cells[0].cell_id[0]);
+
+    let overbooked = false;
+    try {
+      await carol.cells[0].callZome({
+        zome_name: 'syn',
+        fn_name: 'reserve_delivery_slot',
+        payload: { cart_id: carolCartId, slot_hash: slotHash },
+      });
+      overbooked = true;
+    } catch (e) {
+      assert.ok(JSON.stringify(e).includes('Delivery slot is full'));
+    }
+    assert.notOk(overbooked);
+
+    const availableSlots: Array<any> = await store.cells[0].callZome({
+      zome_name: 'syn',
+      fn_name: 'get_available_slots',
+      payload: null,
+    });
+    assert.equal(availableSlots.length, 0);
+  });
+});
+
+test('a reservation lost to an earlier one fails at checkout', async () => {
+  await runScenario(async scenario => {
+    const { player: store, bundle } = await addStoreAgent(scenario);
+    const [bob, carol] = await scenario.addPlayersWithApps([
+      { appBundleSource: { bundle } },
+      { appBundleSource: { bundle } },
+    ]);
+    await scenario.shareAllAgents();
+
+    const now = Date.now() * 1000;
+    const slot: any = await callSyn(store, 'create_delivery_slot', {
+      kind: 'Delivery',
+      location: 'Main street',
+      starts_at: now + 3600 * 1000 * 1000,
+      ends_at: now + 2 * 3600 * 1000 * 1000,
+      capacity: 1,
+    });
+    const slotHash = slot.signed_action.hashed.hash;
+    const bobCartId = await createCart(bob);
+    const carolCartId = await createCart(carol);
+    await dhtSync([store, bob, carol], bob.cells[0].cell_id[0]);
+
+    // Neither has seen the other's reservation yet, so both may think they got the last place
+    const reservations = await Promise.allSettled([
+      callSyn(bob, 'reserve_delivery_slot', {
+        cart_id: bobCartId,
+        slot_hash: slotHash,
+      }),
+      callSyn(carol, 'reserve_delivery_slot', {
+        cart_id: carolCartId,
+        slot_hash: slotHash,
+      }),
+    ]);
+    await dhtSync([store, bob, carol], bob.cells[0].cell_id[0]);
+
+    const checkouts = await Promise.allSettled(
+      [
+        [bob, bobCartId],
+        [carol, carolCartId],
+      ]
+        .filter((_, i) => reservations[i].status === 'fulfilled')
+        .map(([player, cartId]) =>
+          callSyn(player as typeof bob, 'checkout_cart', cartId)
+        )
+    );
+    assert.equal(
+      checkouts.filter(checkout => checkout.status === 'fulfilled').length,
+      1
+    );
+  });
+});
+
+test('commit history is paged newest first', async () => {
+  await runScenario(async scenario => {
+    const [alice] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+    ]);
+    const aliceSyn = new SynStore(
+      new SynClient(alice.appWs as any, 'syn-test')
+    );
+
+    const documentStore = await aliceSyn.createDocument(
+      sampleGrammar.initialState()
+    );
+
+    const commitHashes = [];
+    for (let i = 0; i < 5; i++) {
+      const commit = await aliceSyn.client.createCommit({
+        state: new TextEncoder().encode(JSON.stringify({ count: i })),
+        document_hash: documentStore.documentHash,
+        previous_commit_hashes: commitHashes.slice(-1),
+        authors: [alice.agentPubKey],
+        witnesses: [],
+        meta: undefined,
+      });
+      commitHashes.push(commit.actionHash);
+    }
+    const tip = commitHashes[commitHashes.length - 1];
+
+    const firstPage = await aliceSyn.client.getCommitHistory(
+      tip,
+      undefined,
+      undefined,
+      2
+    );
+    assert.ok(firstPage.next_cursor);
+    assert.deepEqual(
+      firstPage.commits.map(c => c.commit_hash.toString()),
+      [commitHashes[4].toString(), commitHashes[3].toString()]
+    );
+
+    const secondPage = await aliceSyn.client.getCommitHistory(
+      tip,
+      undefined,
+      firstPage.next_cursor,
+      2
+    );
+    assert.deepEqual(
+      secondPage.commits.map(c => c.commit_hash.toString()),
+      [commitHashes[2].toString(), commitHashes[1].toString()]
+    );
+
+    const lastPage = await aliceSyn.client.getCommitHistory(
+      tip,
+      undefined,
+      secondPage.next_cursor,
+      2
+    );
+    assert.equal(lastPage.commits.length, 1);
+    assert.notOk(lastPage.next_cursor);
+    assert.equal(
+      lastPage.commits[0].commit_hash.toString(),
+      commitHashes[0].toString()
+    );
+
+    const shallow = await aliceSyn.client.getCommitHistory(tip, 1);
+    assert.equal(shallow.commits.length, 2);
+    assert.notOk(shallow.next_cursor);
+  });
+});
+
+test('commits between snapshots only store the changes since the last one', async () => {
+  await runScenario(async scenario => {
+    const alice = await scenario.addPlayerWithApp({
+      bundle: synBundle({ snapshot_interval: 2 }),
+    });
+    const aliceSyn = new SynStore(
+      new SynClient(alice.appWs as any, 'syn-test')
+    );
+
+    const documentStore = await aliceSyn.createDocument(
+      sampleGrammar.initialState()
+    );
+    const workspaceStore = await documentStore.createWorkspace(
+      'main',
+      undefined
+    );
+    const sessionStore = await workspaceStore.joinSession();
+
+    const commits = [];
+    for (const title of ['one', 'two', 'three', 'four']) {
+      sessionStore.change(state => (state.title = title));
+      await sessionStore.commitChanges();
+      commits.push(get(sessionStore.currentTip)!);
+    }
+
+    assert.deepEqual(
+      commits.map(c => c.entry.kind?.type),
+      ['Snapshot', 'Delta', 'Delta', 'Snapshot']
+    );
+    for (const delta of commits.slice(1, 3)) {
+      assert.equal(
+        (delta.entry.kind as any).base.toString(),
+        commits[0].actionHash.toString()
+      );
+    }
+
+    const state = await loadCommitState(aliceSyn.client, commits[2]);
+    assert.equal((state as any).title, 'three');
+    assert.deepEqual(
+      await loadCommitState(aliceSyn.client, commits[3]),
+      get(sessionStore.state)
+    );
+
+    // A delta can only build on a snapshot it descends from
+    const unrelated = await aliceSyn.client.createCommit({
+      state: new TextEncoder().encode('{}'),
+      document_hash: documentStore.documentHash,
+      previous_commit_hashes: [],
+      authors: [alice.agentPubKey],
+      witnesses: [],
+      meta: undefined,
+    });
+    let accepted = true;
+    try {
+      await aliceSyn.client.createCommit({
+        state: new Uint8Array(),
+        document_hash: documentStore.documentHash,
+        previous_commit_hashes: [unrelated.actionHash],
+        authors: [alice.agentPubKey],
+        witnesses: [],
+        meta: undefined,
+        kind: { type: 'Delta', base: commits[3].actionHash },
+      });
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+
+    await sessionStore.leaveSession();
+  });
+});
+
+test('states bigger than an entry are split into chunks', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    // Two and a half chunks of 1MiB
+    const state = new Uint8Array(2.5 * 1024 * 1024).map((_, i) => i % 251);
+
+    const document = await aliceClient.createDocument({
+      initial_state: state,
+      meta: undefined,
+    });
+    assert.equal(document.entry.state_chunks?.length, 3);
+    assert.equal(document.entry.initial_state.length, 0);
+
+    const commit = await aliceClient.createCommit({
+      state,
+      document_hash: document.entryHash,
+      previous_commit_hashes: [],
+      authors: [alice.agentPubKey],
+      witnesses: [],
+      meta: undefined,
+    });
+    assert.equal(commit.entry.state_chunks?.length, 3);
+
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    const documentWithState = await bobClient.getDocumentWithState(
+      document.entryHash
+    );
+    assert.deepEqual(new Uint8Array(documentWithState!.state), state);
+    assert.equal(
+      documentWithState!.record.actionHash.toString(),
+      document.actionHash.toString()
+    );
+
+    const commitWithState = await bobClient.getCommitWithState(
+      commit.actionHash
+    );
+    assert.deepEqual(new Uint8Array(commitWithState!.state), state);
+
+    // Small states stay inline
+    const small = await aliceClient.createCommit({
+      state: new TextEncoder().encode('{}'),
+      document_hash: document.entryHash,
+      previous_commit_hashes: [commit.actionHash],
+      authors: [alice.agentPubKey],
+      witnesses: [],
+      meta: undefined,
+    });
+    assert.notOk(small.entry.state_chunks?.length);
+  });
+});
+
+test('participants holding the committed state attest to the commit', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceSyn = new SynStore(
+      new SynClient(alice.appWs as any, 'syn-test')
+    );
+    const bobSyn = new SynStore(new SynClient(bob.appWs as any, 'syn-test'));
+
+    const aliceDocumentStore = await aliceSyn.createDocument(
+      sampleGrammar.initialState()
+    );
+    const aliceWorkspaceStore = await aliceDocumentStore.createWorkspace(
+      'main',
+      undefined
+    );
+    const workspaceHash = aliceWorkspaceStore.workspaceHash;
+    const aliceSessionStore = await aliceWorkspaceStore.joinSession();
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    const bobSessionStore = await bobSyn.documents
+      .get(aliceDocumentStore.documentHash)
+      .workspaces.get(workspaceHash)
+      .joinSession();
+    await waitForOtherParticipants(aliceSessionStore, 1);
+    await waitForOtherParticipants(bobSessionStore, 1);
+
+    aliceSessionStore.change(state => (state.title = 'Attested'));
+    await delay(2000);
+    await aliceSessionStore.commitChanges();
+    await delay(3000);
+
+    const commitHash = get(aliceSessionStore.currentTip)!.actionHash;
+    const attestations = await aliceSyn.client.getCommitAttestations(
+      commitHash
+    );
+    assert.equal(attestations.length, 1);
+    assert.equal(
+      attestations[0].witness.toString(),
+      bob.agentPubKey.toString()
+    );
+    assert.ok(attestations[0].heads.length > 0);
+
+    await bobSessionStore.leaveSession();
+    await aliceSessionStore.leaveSession();
+  });
+});
+
+test('session participants elect and hand over the scribe', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob, carol] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+    const carolClient = new SynClient(carol.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob, carol], alice.cells[0].cell_id[0]);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob, carol], alice.cells[0].cell_id[0]);
+
+    // Carol is not in the session
+    let accepted = true;
+    try {
+      await carolClient.electScribe(workspaceHash, { type: 'LowestPubKey' });
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+
+    // Bob answers our heartbeats the fastest, he announces himself once he gets the result
+    const elected = await aliceClient.electScribe(workspaceHash, {
+      type: 'BestLatency',
+      latencies: [{ agent: bob.agentPubKey, latency_ms: 5 }],
+    });
+    assert.equal(elected.toString(), bob.agentPubKey.toString());
+    await delay(1000);
+    await dhtSync([alice, bob, carol], alice.cells[0].cell_id[0]);
+
+    let scribe = await carolClient.getSessionScribe(workspaceHash);
+    assert.equal(scribe!.scribe.toString(), bob.agentPubKey.toString());
+    assert.equal(scribe!.term, 0);
+
+    // Only the scribe hands the role over
+    accepted = true;
+    try {
+      await aliceClient.handOverScribe(workspaceHash, alice.agentPubKey);
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+
+    await bobClient.handOverScribe(workspaceHash, alice.agentPubKey);
+    await dhtSync([alice, bob, carol], alice.cells[0].cell_id[0]);
+
+    scribe = await carolClient.getSessionScribe(workspaceHash);
+    assert.equal(scribe!.scribe.toString(), alice.agentPubKey.toString());
+    assert.equal(scribe!.term, 1);
+  });
+});
+
+test('session participants expire unless they renew their presence', async () => {
+  await runScenario(async scenario => {
+    const bundle = synBundle({ participant_ttl_secs: 4 });
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { bundle } },
+      { appBundleSource: { bundle } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    let participants = await bobClient.getWorkspaceSessionParticipants(
+      workspaceHash
+    );
+    assert.equal(participants.length, 1);
+    // Still live links can only be removed by their participant
+    assert.equal(
+      await bobClient.removeStaleSessionParticipants(workspaceHash),
+      0
+    );
+
+    // Alice crashed without leaving the session
+    await delay(5000);
+
+    participants = await bobClient.getWorkspaceSessionParticipants(
+      workspaceHash
+    );
+    assert.equal(participants.length, 0);
+    assert.equal(
+      await bobClient.removeStaleSessionParticipants(workspaceHash),
+      1
+    );
+
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      await aliceClient.removeStaleSessionParticipants(workspaceHash),
+      0
+    );
+  });
+});
+
+test('missing change notices are requested again and received ones acknowledged', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceSyn = new SynStore(
+      new SynClient(alice.appWs as any, 'syn-test')
+    );
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const documentStore = await aliceSyn.createDocument(
+      sampleGrammar.initialState()
+    );
+    const workspaceStore = await documentStore.createWorkspace(
+      'main',
+      undefined
+    );
+    const workspaceHash = workspaceStore.workspaceHash;
+    const aliceSessionStore = await workspaceStore.joinSession();
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    // Bob speaks the session protocol by hand to control what gets lost
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    const fromAlice: any[] = [];
+    bobClient.onSignal(signal => {
+      if (signal.type === 'SessionMessage')
+        fromAlice.push(signal.message.payload);
+    });
+    const sendChangeNotice = (number: number) =>
+      bobClient.sendMessage([alice.agentPubKey], {
+        workspace_hash: workspaceHash,
+        sequence: { epoch: 1, number },
+        payload: {
+          type: 'ChangeNotice',
+          state_changes: [],
+          ephemeral_changes: [],
+        },
+      });
+
+    await sendChangeNotice(1);
+    // Number 2 gets lost
+    await sendChangeNotice(3);
+    await delay(1000);
+
+    const resendRequest = fromAlice.find(p => p.type === 'ResendReq');
+    assert.ok(resendRequest);
+    assert.deepEqual(resendRequest.sequences, [2]);
+
+    // Acks only cover what arrived without gaps
+    await delay(2000);
+    let acks = fromAlice.filter(p => p.type === 'Ack').map(p => p.sequence);
+    assert.notOk(acks.includes(3));
+
+    await sendChangeNotice(2);
+    await delay(3000);
+    acks = fromAlice.filter(p => p.type === 'Ack').map(p => p.sequence);
+    assert.ok(acks.includes(3));
+
+    await aliceSessionStore.leaveSession();
+  });
+});
+
+test('session messages of agents outside the workspace are dropped', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob, carol] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+    const carolClient = new SynClient(carol.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+
+    const senders: string[] = [];
+    aliceClient.onSignal(signal => {
+      if (signal.type === 'SessionMessage')
+        senders.push(signal.provenance.toString());
+    });
+
+    // Carol never joined the session: not even her JoinSession reaches the UI
+    for (const payload of [
+      { type: 'JoinSession' },
+      { type: 'ChangeNotice', state_changes: [], ephemeral_changes: [] },
+    ] as any[]) {
+      await carolClient.sendMessage([alice.agentPubKey], {
+        workspace_hash: workspaceHash,
+        payload,
+      });
+    }
+
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob, carol], alice.cells[0].cell_id[0]);
+    await bobClient.sendMessage([alice.agentPubKey], {
+      workspace_hash: workspaceHash,
+      payload: { type: 'Heartbeat', known_participants: [] },
+    });
+    await delay(1000);
+
+    assert.notOk(senders.includes(carol.agentPubKey.toString()));
+    assert.ok(senders.includes(bob.agentPubKey.toString()));
+  });
+});
+
+test('direct and app messages reach their recipients', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const received: any[] = [];
+    bobClient.onSignal(signal => {
+      if (signal.type === 'DirectMessage' || signal.type === 'SessionMessage')
+        received.push(signal);
+    });
+
+    // Outside of any workspace
+    await aliceClient.sendDirectMessage(bob.agentPubKey, {
+      kind: 'wave',
+      payload: new TextEncoder().encode('hi bob'),
+    });
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    await aliceClient.joinWorkspaceSession(workspace.entryHash);
+    await bobClient.joinWorkspaceSession(workspace.entryHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    await aliceClient.sendMessage([bob.agentPubKey], {
+      workspace_hash: workspace.entryHash,
+      payload: {
+        type: 'App',
+        kind: 'cursor',
+        payload: new TextEncoder().encode('line 3'),
+      },
+    });
+    await delay(1000);
+
+    const direct = received.find(s => s.type === 'DirectMessage');
+    assert.ok(direct);
+    assert.equal(direct.provenance.toString(), alice.agentPubKey.toString());
+    assert.equal(direct.message.kind, 'wave');
+    assert.equal(new TextDecoder().decode(direct.message.payload), 'hi bob');
+
+    const app = received.find(
+      s => s.type === 'SessionMessage' && s.message.payload.type === 'App'
+    );
+    assert.ok(app);
+    assert.equal(app.message.payload.kind, 'cursor');
+    assert.equal(
+      new TextDecoder().decode(app.message.payload.payload),
+      'line 3'
+    );
+  });
+});
+
+test('messages of encrypted workspaces reach members with the session key', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'secret', document_hash: document.entryHash, encrypted: true },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    // Shares a fresh key with bob, whose encryption key is now published
+    await aliceClient.rotateSessionKey(workspaceHash);
+    await delay(2000);
+
+    const received = new Promise<Uint8Array>((resolve, reject) => {
+      const unsubs = bobClient.onSignal(signal => {
+        if (
+          signal.type === 'SessionMessage' &&
+          signal.message.payload.type === 'App'
+        ) {
+          unsubs();
+          resolve(signal.message.payload.payload);
+        }
+      });
+      setTimeout(() => reject('Bob did not receive the message'), 10000);
+    });
+
+    const secret = new TextEncoder().encode('the secret plan');
+    await aliceClient.sendMessage([bob.agentPubKey], {
+      workspace_hash: workspaceHash,
+      payload: {
+        type: 'App',
+        kind: 'note',
+        payload: secret,
+      },
+    });
+
+    assert.equal(new TextDecoder().decode(await received), 'the secret plan');
+  });
+});
+
+test('large session messages are fragmented and reassembled', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    // Pseudo random bytes don't compress, so they end up in several fragments
+    const big = new Uint8Array(200 * 1024);
+    let seed = 42;
+    for (let i = 0; i < big.length; i++) {
+      seed = (seed * 1103515245 + 12345) % 2147483648;
+      big[i] = seed >> 16;
+    }
+
+    const received = new Promise<Uint8Array>((resolve, reject) => {
+      const unsubs = bobClient.onSignal(signal => {
+        if (
+          signal.type === 'SessionMessage' &&
+          signal.message.payload.type === 'App'
+        ) {
+          unsubs();
+          resolve(signal.message.payload.payload);
+        }
+      });
+      setTimeout(() => reject('Bob did not receive the message'), 10000);
+    });
+
+    await aliceClient.sendMessage([bob.agentPubKey], {
+      workspace_hash: workspaceHash,
+      payload: {
+        type: 'App',
+        kind: 'blob',
+        payload: big,
+      },
+    });
+
+    const reassembled = await received;
+    assert.equal(reassembled.length, big.length);
+    assert.deepEqual(Array.from(reassembled), Array.from(big));
+  });
+});
+
+test('fragments arriving in separate signals are reassembled', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    const apps: any[] = [];
+    bobClient.onSignal(signal => {
+      if (
+        signal.type === 'SessionMessage' &&
+        signal.message.payload.type === 'App'
+      )
+        apps.push(signal.message.payload);
+    });
+
+    // Alice fragments the payload herself and sends each fragment on its own, out of order
+    const payload = encode({
+      type: 'App',
+      kind: 'note',
+      payload: new TextEncoder().encode('sent in three pieces'),
+    });
+    const third = Math.ceil(payload.length / 3);
+    const sendFragment = (index: number, total = 3) =>
+      aliceClient.sendMessage([bob.agentPubKey], {
+        workspace_hash: workspaceHash,
+        payload: {
+          type: 'Fragment',
+          message_id: [7, 7, 7],
+          index,
+          total,
+          data: payload.slice(index * third, (index + 1) * third),
+        } as any,
+      });
+
+    await sendFragment(2);
+    await delay(500);
+    await sendFragment(0);
+    await delay(500);
+    // Inconsistent fragments are dropped without breaking the message
+    await sendFragment(1, 4);
+    await sendFragment(5);
+    await delay(500);
+    assert.equal(apps.length, 0);
+
+    await sendFragment(1);
+    await delay(1000);
+
+    assert.equal(apps.length, 1);
+    assert.equal(apps[0].kind, 'note');
+    assert.equal(
+      new TextDecoder().decode(apps[0].payload),
+      'sent in three pieces'
+    );
+  });
+});
+
+test('recorded session messages are replayed in order', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    const sendNote = (client: SynClient, to: any, note: string) =>
+      client.sendMessage([to], {
+        workspace_hash: workspaceHash,
+        payload: {
+          type: 'App',
+          kind: 'note',
+          payload: new TextEncoder().encode(note),
+        },
+      });
+
+    assert.notOk(await aliceClient.isRecordingSession(workspaceHash));
+    await sendNote(bobClient, alice.agentPubKey, 'before');
+    await delay(500);
+
+    await aliceClient.startSessionRecording(workspaceHash);
+    assert.ok(await aliceClient.isRecordingSession(workspaceHash));
+    await sendNote(bobClient, alice.agentPubKey, 'one');
+    await delay(500);
+    await sendNote(aliceClient, bob.agentPubKey, 'two');
+    await delay(500);
+    await sendNote(bobClient, alice.agentPubKey, 'three');
+    await delay(500);
+
+    await aliceClient.stopSessionRecording(workspaceHash);
+    assert.notOk(await aliceClient.isRecordingSession(workspaceHash));
+    await sendNote(bobClient, alice.agentPubKey, 'after');
+    await delay(500);
+
+    const notes = (messages: any[]) =>
+      messages.map(m => [
+        m.provenance.toString(),
+        new TextDecoder().decode(m.message.payload.payload),
+      ]);
+
+    const firstPage = await aliceClient.replaySession(workspaceHash, 0, 2);
+    assert.equal(firstPage.total, 3);
+    assert.ok(firstPage.has_more);
+    assert.deepEqual(notes(firstPage.messages), [
+      [bob.agentPubKey.toString(), 'one'],
+      [alice.agentPubKey.toString(), 'two'],
+    ]);
+
+    const secondPage = await aliceClient.replaySession(workspaceHash, 2, 2);
+    assert.notOk(secondPage.has_more);
+    assert.deepEqual(notes(secondPage.messages), [
+      [bob.agentPubKey.toString(), 'three'],
+    ]);
+
+    // Bob never recorded anything
+    const bobReplay = await bobClient.replaySession(workspaceHash);
+    assert.equal(bobReplay.total, 0);
+  });
+});
+
+test('workspace roles are enforced', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const root = await aliceClient.createCommit({
+      state: new TextEncoder().encode('{}'),
+      document_hash: document.entryHash,
+      previous_commit_hashes: [],
+      authors: [alice.agentPubKey],
+      witnesses: [],
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      root.actionHash
+    );
+    const workspaceHash = workspace.entryHash;
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    assert.equal(
+      await bobClient.getWorkspaceRole(workspaceHash, alice.agentPubKey),
+      'Owner'
+    );
+    // Open workspace: everyone edits until roles are assigned
+    assert.equal(
+      await bobClient.getWorkspaceRole(workspaceHash, bob.agentPubKey),
+      'Editor'
+    );
+
+    await aliceClient.assignWorkspaceRole(
+      workspaceHash,
+      bob.agentPubKey,
+      'Viewer'
+    );
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      await bobClient.getWorkspaceRole(workspaceHash, bob.agentPubKey),
+      'Viewer'
+    );
+
+    const rejects = async (call: () => Promise<any>) => {
+      try {
+        await call();
+      } catch (e) {
+        return true;
+      }
+      return false;
+    };
+
+    assert.ok(
+      await rejects(() =>
+        bobClient.assignWorkspaceRole(workspaceHash, bob.agentPubKey, 'Editor')
+      )
+    );
+    assert.ok(
+      await rejects(() =>
+        bobClient.updateWorkspaceTip(workspaceHash, root.actionHash, [])
+      )
+    );
+    assert.ok(
+      await rejects(() => bobClient.renameWorkspace(workspaceHash, 'mine'))
+    );
+    assert.ok(await rejects(() => bobClient.deleteWorkspace(workspaceHash)));
+
+    // Promoted to editor, bob can move the tip again
+    await aliceClient.assignWorkspaceRole(
+      workspaceHash,
+      bob.agentPubKey,
+      'Editor'
+    );
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      await bobClient.getWorkspaceRole(workspaceHash, bob.agentPubKey),
+      'Editor'
+    );
+    assert.notOk(
+      await rejects(() =>
+        bobClient.updateWorkspaceTip(workspaceHash, root.actionHash, [])
+      )
+    );
+  });
+});
+
+test('viewers are never elected scribe', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new TextEncoder().encode('{}'),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    await aliceClient.assignWorkspaceRole(
+      workspaceHash,
+      bob.agentPubKey,
+      'Viewer'
+    );
+    await aliceClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    // Bob answers the fastest, but he can't commit for everyone
+    const elected = await aliceClient.electScribe(workspaceHash, {
+      type: 'BestLatency',
+      latencies: [{ agent: bob.agentPubKey, latency_ms: 5 }],
+    });
+    assert.equal(elected.toString(), alice.agentPubKey.toString());
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    const scribe = await bobClient.getSessionScribe(workspaceHash);
+    assert.equal(scribe!.scribe.toString(), alice.agentPubKey.toString());
+
+    let accepted = true;
+    try {
+      await aliceClient.handOverScribe(workspaceHash, bob.agentPubKey);
+    } catch (e) {
+      accepted = false;
+    }
+    assert.notOk(accepted);
+  });
+});
+
+test('workspaces are archived and deleted by their creator once nobody is in them', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const workspace = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const workspaceHash = workspace.entryHash;
+    const fails = async (call: () => Promise<any>) => {
+      try {
+        await call();
+      } catch (e) {
+        return true;
+      }
+      return false;
+    };
+
+    await bobClient.joinWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.ok(await fails(() => aliceClient.archiveWorkspace(workspaceHash)));
+
+    await bobClient.leaveWorkspaceSession(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    // Only alice linked the workspace to the document
+    assert.ok(await fails(() => bobClient.archiveWorkspace(workspaceHash)));
+    await aliceClient.archiveWorkspace(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    assert.equal(
+      (await bobClient.getWorkspacesForDocument(document.entryHash)).length,
+      0
+    );
+    assert.equal(
+      (await bobClient.getAllWorkspacesForDocument(document.entryHash)).length,
+      1
+    );
+
+    await aliceClient.unarchiveWorkspace(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      (await bobClient.getWorkspacesForDocument(document.entryHash)).length,
+      1
+    );
+
+    assert.ok(await fails(() => bobClient.deleteWorkspace(workspaceHash)));
+    await aliceClient.deleteWorkspace(workspaceHash);
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      (await bobClient.getAllWorkspacesForDocument(document.entryHash)).length,
+      0
+    );
+  });
+});
+
+test('workspaces are found by their current and unique name', async () => {
+  await runScenario(async scenario => {
+    const [alice, bob] = await scenario.addPlayersWithApps([
+      { appBundleSource: { path: synHapp } },
+      { appBundleSource: { path: synHapp } },
+    ]);
+    await scenario.shareAllAgents();
+    const aliceClient = new SynClient(alice.appWs as any, 'syn-test');
+    const bobClient = new SynClient(bob.appWs as any, 'syn-test');
+
+    const document = await aliceClient.createDocument({
+      initial_state: new Uint8Array(),
+      meta: undefined,
+    });
+    const main = await aliceClient.createWorkspace(
+      { name: 'main', document_hash: document.entryHash },
+      undefined
+    );
+    const draft = await aliceClient.createWorkspace(
+      { name: 'draft', document_hash: document.entryHash },
+      undefined
+    );
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+
+    assert.equal(
+      (await bobClient.getWorkspaceByName(document.entryHash, 'main'))!.toString(),
+      main.entryHash.toString()
+    );
+    const fails = async (call: () => Promise<any>) => {
+      try {
+        await call();
+      } catch (e) {
+        return true;
+      }
+      return false;
+    };
+    assert.ok(
+      await fails(() =>
+        bobClient.createWorkspace(
+          { name: 'main', document_hash: document.entryHash, encrypted: true },
+          undefined
+        )
+      )
+    );
+    assert.ok(
+      await fails(() => aliceClient.renameWorkspace(draft.entryHash, 'main'))
+    );
+    // Only the creator renames
+    assert.ok(
+      await fails(() => bobClient.renameWorkspace(draft.entryHash, 'final'))
+    );
+
+    await aliceClient.renameWorkspace(draft.entryHash, 'final');
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      (await bobClient.getWorkspaceByName(document.entryHash, 'final'))!.toString(),
+      draft.entryHash.toString()
+    );
+    assert.notOk(
+      await bobClient.getWorkspaceByName(document.entryHash, 'draft')
+    );
+
+    // The old name is free again
+    const newDraft = await bobClient.createWorkspace(
+      { name: 'draft', document_hash: document.entryHash },
+      undefined
+    );
+    await dhtSync([alice, bob], alice.cells[0].cell_id[0]);
+    assert.equal(
+      (await aliceClient.getWorkspaceByName(document.entryHash, 'draft'))!.toString(),
+      newDraft.entryHash.toString()
+    );
+  });
+});
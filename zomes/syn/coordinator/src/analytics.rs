@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashSet};
+
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::cart::get_agent_carts_with_status;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpendBucket {
+    pub category: String,
+    pub subcategory: Option<String>,
+    /// `YYYY-MM` of the cart's checkout
+    pub month: String,
+    pub total: f64,
+    pub item_count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpendAnalytics {
+    pub buckets: Vec<SpendBucket>,
+    pub total: f64,
+    pub item_count: u32,
+    pub cart_count: u32,
+}
+
+/// Aggregates the orders of all of the caller's processed carts by category, subcategory and month,
+/// at the prices they were checked out with. Carts processed without a checkout have no order and
+/// are left out.
+#[hdk_extern]
+pub fn get_spend_analytics(_: ()) -> ExternResult<SpendAnalytics> {
+    let processed_carts: HashSet<(DnaHash, Timestamp)> =
+        get_agent_carts_with_status(CartStatus::Processed)?
+            .into_iter()
+            .map(|(_, cart)| (cart.cart_dna_hash, cart.created_at))
+            .collect();
+
+    let mut buckets: BTreeMap<(String, String, Option<String>), (f64, u32)> = BTreeMap::new();
+    let mut total = 0.0;
+    let mut item_count = 0;
+    let mut cart_count = 0;
+
+    for order in get_own_orders()? {
+        let Some(checked_out_cart) = get(order.cart_hash.clone(), GetOptions::default())?
+            .and_then(|record| record.entry().to_app_option::<Cart>().ok()?)
+        else {
+            warn!("Cart {} of an order not found", order.cart_hash);
+            continue;
+        };
+        if !processed_carts.contains(&(
+            checked_out_cart.cart_dna_hash,
+            checked_out_cart.created_at,
+        )) {
+            continue;
+        }
+
+        let month = timestamp_month(order.checked_out_at);
+        cart_count += 1;
+        total += order.total;
+
+        for line in order.lines {
+            let line_total = line.unit_price as f64 * line.quantity as f64;
+
+            let bucket = buckets
+                .entry((month.clone(), line.category, line.subcategory))
+                .or_insert((0.0, 0));
+            bucket.0 += line_total;
+            bucket.1 += line.quantity;
+
+            item_count += line.quantity;
+        }
+    }
+
+    Ok(SpendAnalytics {
+        buckets: buckets
+            .into_iter()
+            .map(|((month, category, subcategory), (total, item_count))| SpendBucket {
+                category,
+                subcategory,
+                month,
+                total,
+                item_count,
+            })
+            .collect(),
+        total,
+        item_count,
+        cart_count,
+    })
+}
+
+fn get_own_orders() -> ExternResult<Vec<Order>> {
+    let records = query(
+        ChainQueryFilter::new()
+            .entry_type(UnitEntryTypes::Order.try_into()?)
+            .include_entries(true),
+    )?;
+
+    Ok(records
+        .into_iter()
+        .filter_map(|record| record.entry().to_app_option::<Order>().ok()?)
+        .collect())
+}
+
+/// Formats the UTC month of a timestamp as `YYYY-MM`
+fn timestamp_month(timestamp: Timestamp) -> String {
+    // Days since the unix epoch to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = timestamp.as_micros().div_euclid(86_400_000_000) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}", year, month)
+}
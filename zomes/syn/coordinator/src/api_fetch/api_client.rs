@@ -46,6 +46,7 @@ pub fn create_product_document(retail_product: RetailProduct) -> ExternResult<Re
     let document = Document {
         initial_state: SerializedBytes::from(UnsafeBytes::from(product_bytes)),
         meta: None,
+        state_chunks: vec![],
     };
 
     let document_hash = create_entry(EntryTypes::Document(document.clone()))?;
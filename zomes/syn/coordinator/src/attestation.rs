@@ -0,0 +1,132 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{
+    history::get_commit_entry,
+    messages::{send_message, MessagePayload, SendMessageInput, SessionMessage},
+    utils::create_link_relaxed,
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AttestCommitInput {
+    pub workspace_hash: EntryHash,
+    pub commit_hash: ActionHash,
+    /// Heads of the state the caller holds, which must be the commit's state
+    pub heads: Vec<String>,
+}
+
+/// Signs the commit together with the heads of the caller's state and sends the attestation
+/// to the commit's author to link it. Syn can't read the states, so checking that the heads
+/// are those of the commit's state is up to the caller and to whoever reads the attestations.
+#[hdk_extern]
+pub fn attest_commit(input: AttestCommitInput) -> ExternResult<Signature> {
+    let (record, _) = get_commit_entry(input.commit_hash.clone())?.ok_or(wasm_error!(
+        WasmErrorInner::Guest(format!("Commit {} not found", input.commit_hash))
+    ))?;
+
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let signature = sign(
+        my_pub_key.clone(),
+        AttestedState {
+            commit_hash: input.commit_hash.clone(),
+            heads: input.heads.clone(),
+        },
+    )?;
+
+    let author = record.action().author().clone();
+    if author.eq(&my_pub_key) {
+        add_commit_attestation(input.commit_hash, my_pub_key, input.heads, signature.clone())?;
+    } else {
+        send_message(SendMessageInput {
+            message: SessionMessage {
+                workspace_hash: input.workspace_hash,
+                sequence: None,
+                payload: MessagePayload::CommitAttestation {
+                    commit_hash: input.commit_hash,
+                    heads: input.heads,
+                    signature: signature.clone(),
+                },
+            },
+            recipients: vec![author],
+        })?;
+    }
+
+    Ok(signature)
+}
+
+/// Links a witness' attestation to one of our own commits, ignoring invalid signatures
+/// and witnesses that already attested to it
+pub fn add_commit_attestation(
+    commit_hash: ActionHash,
+    witness: AgentPubKey,
+    heads: Vec<String>,
+    signature: Signature,
+) -> ExternResult<()> {
+    let Some((record, _)) = get_commit_entry(commit_hash.clone())? else {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Commit {} not found",
+            commit_hash
+        ))));
+    };
+    if !record
+        .action()
+        .author()
+        .eq(&agent_info()?.agent_initial_pubkey)
+    {
+        return Err(wasm_error!("Only the commit's author links its attestations"));
+    }
+
+    let attestation = AttestationTag {
+        witness,
+        heads,
+        signature,
+    };
+    if !verify_attestation(&commit_hash, &attestation)? {
+        return Err(wasm_error!("The witness signature doesn't match the commit"));
+    }
+
+    let already_attested = get_commit_attestations(commit_hash.clone())?
+        .into_iter()
+        .any(|existing| existing.witness.eq(&attestation.witness));
+    if already_attested {
+        return Ok(());
+    }
+
+    let tag = SerializedBytes::try_from(attestation.clone()).map_err(|err| wasm_error!(err))?;
+    create_link_relaxed(
+        commit_hash,
+        attestation.witness,
+        LinkTypes::CommitToAttestations,
+        tag.bytes().clone(),
+    )?;
+
+    Ok(())
+}
+
+/// Attestations linked to the commit with valid signatures, one per witness
+#[hdk_extern]
+pub fn get_commit_attestations(commit_hash: ActionHash) -> ExternResult<Vec<AttestationTag>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(commit_hash, LinkTypes::CommitToAttestations)?.build(),
+    )?;
+
+    let mut attestations: Vec<AttestationTag> = Vec::new();
+    for link in links {
+        let Ok(attestation) = AttestationTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            link.tag.into_inner(),
+        ))) else {
+            continue;
+        };
+        if attestations
+            .iter()
+            .any(|existing| existing.witness.eq(&attestation.witness))
+        {
+            continue;
+        }
+        if verify_attestation(&commit_hash, &attestation)? {
+            attestations.push(attestation);
+        }
+    }
+
+    Ok(attestations)
+}
@@ -142,6 +142,12 @@ pub fn create_cart_entry(entry_input: CreateCartEntryInput) -> ExternResult<Reco
         created_at: entry_input.created_at,
         cart_name: format!("Cart_{}", entry_input.created_at.to_string().split('.').next().unwrap_or("")),
         meta: None,
+        items: vec![],
+        status_history: vec![CartStatusChange {
+            status: CartStatus::Active,
+            changed_at: entry_input.created_at,
+        }],
+        delivery_slot: None,
     };
 
     let action_hash = create_entry(EntryTypes::Cart(cart.clone()))?;
@@ -326,6 +332,10 @@ pub fn delete_cart(input: DeleteCartInput) -> ExternResult<()> {
                       let cart_created_timestamp = cart.created_at.as_micros().to_string();
                       if cart.cart_dna_hash.to_string() == cart_dna_hash && cart_created_timestamp == cart_timestamp {
                           cart.status = CartStatus::Processed;
+                          cart.status_history.push(CartStatusChange {
+                              status: CartStatus::Processed,
+                              changed_at: sys_time()?,
+                          });
                           let new_action_hash = create_entry(EntryTypes::Cart(cart))?;
                           
                           create_link(
@@ -422,6 +432,10 @@ pub fn update_cart_status(input: UpdateCartInput) -> ExternResult<()> {
     .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))? {
                                     let cart_created_timestamp = cart.created_at.as_micros().to_string();
                                     if cart.cart_dna_hash.to_string() == cart_dna_hash && cart_created_timestamp == cart_timestamp {
+    cart.status_history.push(CartStatusChange {
+        status: input.status.clone(),
+        changed_at: sys_time()?,
+    });
     cart.status = input.status;
     let hash = create_entry(EntryTypes::Cart(cart))?;
     delete_link(cart_link.create_link_hash)?;
@@ -442,4 +456,99 @@ pub fn update_cart_status(input: UpdateCartInput) -> ExternResult<()> {
         }
     }
     Err(wasm_error!("Cart not found"))
-}
\ No newline at end of file
+}
+
+pub fn agent_cart_path() -> ExternResult<TypedPath> {
+    Path::from(format!("agent_carts_{}", agent_info()?.agent_initial_pubkey))
+        .typed(LinkTypes::CartPath)
+}
+
+/// Finds the caller's current entry for a `Cart_<dna hash>_<created at micros>` id,
+/// together with the agent path link that points at it
+pub fn find_agent_cart(cart_id: &str) -> ExternResult<Option<(Link, Cart)>> {
+    let parts: Vec<&str> = cart_id.split('_').collect();
+    if parts.len() < 3 {
+        return Ok(None);
+    }
+    let cart_dna_hash = parts[1..parts.len() - 1].join("_");
+    let cart_timestamp = parts[parts.len() - 1];
+
+    let cart_links = get_links(GetLinksInputBuilder::try_new(
+        agent_cart_path()?.path_entry_hash()?,
+        LinkTypes::CartToDocument,
+    )?.build())?;
+
+    for cart_link in cart_links {
+        if let Some(cart_hash) = cart_link.target.clone().into_action_hash() {
+            if let Some(record) = get(cart_hash, GetOptions::default())? {
+                if let Some(cart) = record.entry().to_app_option::<Cart>()
+                    .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))? {
+                    if cart.cart_dna_hash.to_string() == cart_dna_hash
+                        && cart.created_at.as_micros().to_string() == cart_timestamp {
+                        return Ok(Some((cart_link, cart)));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Carts are never updated in place: a new entry is created and the agent path link moved to it
+pub fn replace_agent_cart(cart_link: Link, cart: Cart) -> ExternResult<ActionHash> {
+    let hash = create_entry(EntryTypes::Cart(cart))?;
+    delete_link(cart_link.create_link_hash)?;
+    create_link(
+        agent_cart_path()?.path_entry_hash()?,
+        hash.clone(),
+        LinkTypes::CartToDocument,
+        (),
+    )?;
+    Ok(hash)
+}
+
+/// All of the caller's carts in the given status, including the ones `get_all_carts` hides
+pub fn get_agent_carts_with_status(status: CartStatus) -> ExternResult<Vec<(ActionHash, Cart)>> {
+    let links = get_links(GetLinksInputBuilder::try_new(
+        agent_cart_path()?.path_entry_hash()?,
+        LinkTypes::CartToDocument,
+    )?.build())?;
+
+    let mut carts = Vec::new();
+    for link in links {
+        if let Some(hash) = link.target.into_action_hash() {
+            if let Some(record) = get(hash.clone(), GetOptions::default())? {
+                if let Some(cart) = record.entry().to_app_option::<Cart>()
+                    .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))? {
+                    if cart.status == status {
+                        carts.push((hash, cart));
+                    }
+                }
+            }
+        }
+    }
+    Ok(carts)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetCartItemsInput {
+    pub cart_id: String,
+    pub items: Vec<CartItem>,
+}
+
+#[hdk_extern]
+pub fn set_cart_items(input: SetCartItemsInput) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&input.cart_id)?
+        .ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can be edited"));
+    }
+
+    cart.items = input.items.into_iter().filter(|item| item.quantity > 0).collect();
+    let hash = replace_agent_cart(cart_link, cart)?;
+
+    get(hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
@@ -0,0 +1,182 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::holo_hash::{ActionHashB64, AgentPubKeyB64, AnyDhtHashB64, DnaHashB64};
+use hdk::prelude::*;
+
+use crate::{
+    cart::{agent_cart_path, clone_cart_dna, find_agent_cart, CloneCartInput},
+    catalog::get_catalog_product,
+};
+
+/// Bump whenever the shape of `CartExportDocument` changes, `import_cart` rejects other versions
+pub const CART_EXPORT_VERSION: u32 = 1;
+
+const CSV_HEADER: &str = "product name,size,unit price,quantity,line total";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartExportDocument {
+    pub version: u32,
+    pub cart_name: String,
+    pub original_dna_hash: DnaHashB64,
+    pub cart_dna_hash: DnaHashB64,
+    pub document_hash: AnyDhtHashB64,
+    pub owner: AgentPubKeyB64,
+    pub status: CartStatus,
+    pub created_at: Timestamp,
+    pub status_history: Vec<CartStatusChange>,
+    /// Base64 of the cart's `meta` bytes
+    pub meta: Option<String>,
+    pub items: Vec<CartExportLine>,
+    pub total: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CartExportLine {
+    pub product_hash: ActionHashB64,
+    pub name: String,
+    pub size: String,
+    pub unit_price: f64,
+    pub quantity: u32,
+    pub line_total: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CartExport {
+    pub json: String,
+    pub csv: String,
+}
+
+#[hdk_extern]
+pub fn export_cart(cart_id: String) -> ExternResult<CartExport> {
+    // Carts of any status, processed ones are still exportable for accounting
+    let (_, cart) = find_agent_cart(&cart_id)?.ok_or(wasm_error!("Cart not found"))?;
+
+    let document = cart_export_document(cart)?;
+
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?;
+
+    let mut csv = String::from(CSV_HEADER);
+    for line in document.items.iter() {
+        csv.push_str(&format!(
+            "\n{},{},{:.2},{},{:.2}",
+            csv_field(&line.name),
+            csv_field(&line.size),
+            line.unit_price,
+            line.quantity,
+            line.line_total
+        ));
+    }
+    csv.push('\n');
+
+    Ok(CartExport { json, csv })
+}
+
+fn cart_export_document(cart: Cart) -> ExternResult<CartExportDocument> {
+    let mut items = Vec::new();
+    for item in cart.items {
+        let product = get_catalog_product(item.product_hash.clone())?.ok_or(wasm_error!(
+            WasmErrorInner::Guest(format!("Product {} not found", item.product_hash))
+        ))?;
+        let unit_price = product.price as f64;
+
+        items.push(CartExportLine {
+            product_hash: item.product_hash.into(),
+            name: product.name,
+            size: product.size,
+            unit_price,
+            quantity: item.quantity,
+            line_total: unit_price * item.quantity as f64,
+        });
+    }
+
+    Ok(CartExportDocument {
+        version: CART_EXPORT_VERSION,
+        cart_name: cart.cart_name,
+        original_dna_hash: cart.original_dna_hash.into(),
+        cart_dna_hash: cart.cart_dna_hash.into(),
+        document_hash: cart.document_hash.into(),
+        owner: cart.owner.into(),
+        status: cart.status,
+        created_at: cart.created_at,
+        status_history: cart.status_history,
+        meta: cart.meta.map(|meta| base64::encode(meta.bytes())),
+        total: items.iter().map(|line| line.line_total).sum(),
+        items,
+    })
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Recreates a cart owned by the caller from the JSON produced by `export_cart`.
+/// The import is a new active cart in a clone cell of its own, with its own name and status history.
+#[hdk_extern]
+pub fn import_cart(json: String) -> ExternResult<Record> {
+    let document: CartExportDocument = serde_json::from_str(&json)
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?;
+
+    if document.version != CART_EXPORT_VERSION {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Unsupported cart export version {}, expected {}",
+            document.version, CART_EXPORT_VERSION
+        ))));
+    }
+
+    let meta = match document.meta {
+        Some(meta) => Some(SerializedBytes::from(UnsafeBytes::from(
+            base64::decode(meta).map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?,
+        ))),
+        None => None,
+    };
+
+    // The exporter's cart cell is not ours to use, the import gets a fresh clone and with it
+    // a creation time that keeps its id and name from colliding with the original
+    let document_hash: AnyDhtHash = document.document_hash.into();
+    let clone = clone_cart_dna(CloneCartInput {
+        document_hash: document_hash.clone(),
+        cart_name: document.cart_name,
+        created_at: sys_time()?,
+    })?;
+    let created_at = clone.created_at;
+    let cart = Cart {
+        original_dna_hash: clone.original_dna_hash,
+        cart_dna_hash: clone.cart_dna_hash,
+        document_hash,
+        owner: agent_info()?.agent_initial_pubkey,
+        status: CartStatus::Active,
+        created_at,
+        cart_name: format!("Cart_{}", created_at.to_string().split('.').next().unwrap_or("")),
+        meta,
+        items: document
+            .items
+            .into_iter()
+            .map(|line| CartItem {
+                product_hash: line.product_hash.into(),
+                quantity: line.quantity,
+                assignments: vec![],
+                substitution: SubstitutionPolicy::None,
+            })
+            .collect(),
+        status_history: vec![CartStatusChange {
+            status: CartStatus::Active,
+            changed_at: created_at,
+        }],
+        delivery_slot: None,
+    };
+
+    let action_hash = create_entry(EntryTypes::Cart(cart))?;
+    create_link(
+        agent_cart_path()?.path_entry_hash()?,
+        action_hash.clone(),
+        LinkTypes::CartToDocument,
+        (),
+    )?;
+
+    get(action_hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
@@ -0,0 +1,123 @@
+use hdk::prelude::*;
+
+/// Read-only view of the `Product` entries published by the products zome in this same DNA.
+/// We mirror the fields instead of depending on `products_integrity`, which would pull a
+/// second set of entry and link type externs into this coordinator.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct CatalogProduct {
+    pub name: String,
+    pub price: f32,
+    pub size: String,
+    pub stocks_status: String,
+    pub category: String,
+    pub subcategory: Option<String>,
+    pub product_type: Option<String>,
+    pub image_url: Option<String>,
+}
+
+pub fn get_catalog_product(product_hash: ActionHash) -> ExternResult<Option<CatalogProduct>> {
+    let Some(record) = get(product_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+
+    record
+        .entry()
+        .to_app_option::<CatalogProduct>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))
+}
+
+impl CatalogProduct {
+    /// `stocks_status` is free text coming from the store, e.g. "Many in stock" or "Out of stock"
+    pub fn is_available(&self) -> bool {
+        let status = self.stocks_status.trim().to_lowercase();
+
+        !(status.is_empty()
+            || status.contains("out of stock")
+            || status.contains("sold out")
+            || status.contains("unavailable"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetProductsParams {
+    category: String,
+    subcategory: Option<String>,
+    product_type: Option<String>,
+    offset: usize,
+    limit: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CategorizedProducts {
+    products: Vec<Record>,
+    has_more: bool,
+}
+
+/// How many products to fetch per call while looking for substitutes
+const SAME_TYPE_PAGE_SIZE: usize = 20;
+
+/// Available products under the same `product_type` path of the catalog, excluding the given one.
+/// Pages through the catalog until `limit` of them are found or the type runs out.
+pub fn get_same_type_products(
+    product_hash: &ActionHash,
+    product: &CatalogProduct,
+    limit: usize,
+) -> ExternResult<Vec<(ActionHash, CatalogProduct)>> {
+    // Product type paths only exist below a subcategory, see `create_product` in the products zome
+    let (Some(subcategory), Some(product_type)) = (&product.subcategory, &product.product_type)
+    else {
+        return Ok(vec![]);
+    };
+
+    let mut products = Vec::new();
+    let mut offset = 0;
+    while products.len() < limit {
+        let response = call(
+            CallTargetCell::Local,
+            ZomeName::from("products"),
+            FunctionName::from("get_products_by_category"),
+            None,
+            GetProductsParams {
+                category: product.category.clone(),
+                subcategory: Some(subcategory.clone()),
+                product_type: Some(product_type.clone()),
+                offset,
+                limit: SAME_TYPE_PAGE_SIZE,
+            },
+        )?;
+
+        let page: CategorizedProducts = match response {
+            ZomeCallResponse::Ok(result) => result.decode().map_err(|err| wasm_error!(err))?,
+            _ => {
+                return Err(wasm_error!(WasmErrorInner::Guest(format!(
+                    "Error fetching products of type {}: {:?}",
+                    product_type, response
+                ))))
+            }
+        };
+
+        for record in page.products {
+            let hash = record.action_address().clone();
+            if hash.eq(product_hash) {
+                continue;
+            }
+            if let Some(candidate) = record
+                .entry()
+                .to_app_option::<CatalogProduct>()
+                .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?
+            {
+                if candidate.is_available() {
+                    products.push((hash, candidate));
+                }
+            }
+        }
+
+        if !page.has_more {
+            break;
+        }
+        offset += SAME_TYPE_PAGE_SIZE;
+    }
+    products.truncate(limit);
+
+    Ok(products)
+}
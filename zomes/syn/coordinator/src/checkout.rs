@@ -0,0 +1,237 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{
+    cart::{find_agent_cart, replace_agent_cart},
+    catalog::{get_catalog_product, get_same_type_products},
+    delivery::holds_slot_reservation,
+};
+
+/// How many alternates are proposed for each unavailable product
+const MAX_SUBSTITUTES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AssignCartItemInput {
+    pub cart_id: String,
+    pub product_hash: ActionHash,
+    pub shares: Vec<ItemShare>,
+}
+
+#[hdk_extern]
+pub fn assign_cart_item(input: AssignCartItemInput) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&input.cart_id)?
+        .ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can be edited"));
+    }
+
+    let item = cart
+        .items
+        .iter_mut()
+        .find(|item| item.product_hash == input.product_hash)
+        .ok_or(wasm_error!("Product is not in this cart"))?;
+    item.assignments = input.shares.into_iter().filter(|share| share.ratio > 0).collect();
+
+    let hash = replace_agent_cart(cart_link, cart)?;
+
+    get(hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetItemSubstitutionInput {
+    pub cart_id: String,
+    pub product_hash: ActionHash,
+    pub substitution: SubstitutionPolicy,
+}
+
+#[hdk_extern]
+pub fn set_item_substitution(input: SetItemSubstitutionInput) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&input.cart_id)?
+        .ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can be edited"));
+    }
+
+    let item = cart
+        .items
+        .iter_mut()
+        .find(|item| item.product_hash == input.product_hash)
+        .ok_or(wasm_error!("Product is not in this cart"))?;
+    item.substitution = input.substitution;
+
+    let hash = replace_agent_cart(cart_link, cart)?;
+
+    get(hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+/// For every unavailable product in the cart, the alternates its substitution policy allows
+pub fn propose_substitutions(cart: &Cart) -> ExternResult<Vec<SubstitutionProposal>> {
+    let mut proposals = Vec::new();
+
+    for item in cart.items.iter() {
+        let product = get_catalog_product(item.product_hash.clone())?.ok_or(wasm_error!(
+            WasmErrorInner::Guest(format!("Product {} not found", item.product_hash))
+        ))?;
+        if product.is_available() {
+            continue;
+        }
+
+        let substitutes = match &item.substitution {
+            SubstitutionPolicy::None => vec![],
+            SubstitutionPolicy::AnySameType => {
+                get_same_type_products(&item.product_hash, &product, MAX_SUBSTITUTES)?
+                    .into_iter()
+                    .map(|(hash, _)| hash)
+                    .collect()
+            }
+            SubstitutionPolicy::Specific { product_hash } => {
+                match get_catalog_product(product_hash.clone())? {
+                    Some(alternate) if alternate.is_available() => vec![product_hash.clone()],
+                    _ => vec![],
+                }
+            }
+        };
+
+        proposals.push(SubstitutionProposal {
+            product_hash: item.product_hash.clone(),
+            substitutes,
+        });
+    }
+
+    Ok(proposals)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BillSplit {
+    pub shares: Vec<AgentShare>,
+    pub total: f64,
+}
+
+#[hdk_extern]
+pub fn get_bill_split(cart_id: String) -> ExternResult<BillSplit> {
+    let (_, cart) = find_agent_cart(&cart_id)?.ok_or(wasm_error!("Cart not found"))?;
+
+    compute_bill_split(&cart)
+}
+
+/// Splits every line's catalog price among its assignees by ratio,
+/// unassigned lines are paid for by the cart owner
+pub fn compute_bill_split(cart: &Cart) -> ExternResult<BillSplit> {
+    Ok(split_bill(cart, &price_cart_items(cart)?))
+}
+
+/// The cart's items with their current catalog price and category
+pub fn price_cart_items(cart: &Cart) -> ExternResult<Vec<OrderLine>> {
+    cart.items
+        .iter()
+        .map(|item| {
+            let product = get_catalog_product(item.product_hash.clone())?.ok_or(wasm_error!(
+                WasmErrorInner::Guest(format!("Product {} not found", item.product_hash))
+            ))?;
+            Ok(OrderLine {
+                product_hash: item.product_hash.clone(),
+                quantity: item.quantity,
+                unit_price: product.price,
+                category: product.category,
+                subcategory: product.subcategory,
+            })
+        })
+        .collect()
+}
+
+fn split_bill(cart: &Cart, lines: &[OrderLine]) -> BillSplit {
+    let mut shares: Vec<AgentShare> = Vec::new();
+    let mut total = 0.0;
+
+    let mut add_share = |agent: &AgentPubKey, amount: f64| {
+        match shares.iter_mut().find(|share| share.agent.eq(agent)) {
+            Some(share) => share.amount += amount,
+            None => shares.push(AgentShare {
+                agent: agent.clone(),
+                amount,
+            }),
+        }
+    };
+
+    for (item, line) in cart.items.iter().zip(lines.iter()) {
+        let line_total = line.unit_price as f64 * item.quantity as f64;
+        total += line_total;
+
+        let ratio_sum: u32 = item.assignments.iter().map(|share| share.ratio).sum();
+        if ratio_sum == 0 {
+            add_share(&cart.owner, line_total);
+            continue;
+        }
+
+        for assignment in item.assignments.iter() {
+            add_share(
+                &assignment.agent,
+                line_total * assignment.ratio as f64 / ratio_sum as f64,
+            );
+        }
+    }
+
+    BillSplit { shares, total }
+}
+
+/// Marks the cart as checked out and records an `Order` with the bill split at this point,
+/// along with substitutes for any product that went out of stock and the reserved delivery slot
+#[hdk_extern]
+pub fn checkout_cart(cart_id: String) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&cart_id)?.ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can be checked out"));
+    }
+    if let Some(slot_hash) = &cart.delivery_slot {
+        if !holds_slot_reservation(slot_hash.clone(), &cart_id)? {
+            return Err(wasm_error!(
+                "The delivery slot went to an earlier reservation, pick another one"
+            ));
+        }
+    }
+
+    let lines = price_cart_items(&cart)?;
+    let bill_split = split_bill(&cart, &lines);
+    let substitutions = propose_substitutions(&cart)?;
+    let checked_out_at = sys_time()?;
+
+    cart.status = CartStatus::CheckedOut;
+    cart.status_history.push(CartStatusChange {
+        status: CartStatus::CheckedOut,
+        changed_at: checked_out_at,
+    });
+    let cart_hash = replace_agent_cart(cart_link, cart.clone())?;
+
+    let order = Order {
+        cart_hash: cart_hash.clone(),
+        document_hash: cart.document_hash,
+        owner: cart.owner,
+        checked_out_at,
+        items: cart.items,
+        total: bill_split.total,
+        bill_split: bill_split.shares,
+        substitutions,
+        delivery_slot: cart.delivery_slot,
+        lines,
+    };
+    let order_hash = create_entry(EntryTypes::Order(order))?;
+    create_link(cart_hash, order_hash.clone(), LinkTypes::CartToOrder, ())?;
+
+    get(order_hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+#[hdk_extern]
+pub fn get_order_for_cart(cart_hash: ActionHash) -> ExternResult<Option<Record>> {
+    let links = get_links(GetLinksInputBuilder::try_new(cart_hash, LinkTypes::CartToOrder)?.build())?;
+
+    match links.into_iter().next().and_then(|link| link.target.into_action_hash()) {
+        Some(order_hash) => get(order_hash, GetOptions::default()),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,94 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::utils::create_relaxed;
+
+/// Leaves states up to `STATE_CHUNK_SIZE` inline, otherwise stores them as
+/// `StateChunk` entries and returns an empty inline state with the chunk hashes
+pub fn split_state(state: SerializedBytes) -> ExternResult<(SerializedBytes, Vec<EntryHash>)> {
+    if state.bytes().len() <= STATE_CHUNK_SIZE {
+        return Ok((state, vec![]));
+    }
+
+    let mut chunk_hashes = Vec::new();
+    for data in state.bytes().chunks(STATE_CHUNK_SIZE) {
+        let chunk = StateChunk {
+            data: SerializedBytes::from(UnsafeBytes::from(data.to_vec())),
+        };
+        chunk_hashes.push(hash_entry(&chunk)?);
+        create_relaxed(EntryTypes::StateChunk(chunk.clone()), chunk.try_into()?)?;
+    }
+
+    Ok((SerializedBytes::from(UnsafeBytes::from(vec![])), chunk_hashes))
+}
+
+/// Concatenates the given chunks back into the original state
+pub fn load_state(state_chunks: &[EntryHash]) -> ExternResult<SerializedBytes> {
+    let mut bytes = Vec::new();
+    for chunk_hash in state_chunks {
+        let record = get(chunk_hash.clone(), GetOptions::default())?.ok_or(wasm_error!(
+            WasmErrorInner::Guest(format!("State chunk {} not found", chunk_hash))
+        ))?;
+        let chunk = record
+            .entry()
+            .to_app_option::<StateChunk>()
+            .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?
+            .ok_or(wasm_error!(WasmErrorInner::Guest(format!(
+                "Entry {} is not a state chunk",
+                chunk_hash
+            ))))?;
+        bytes.extend_from_slice(chunk.data.bytes());
+    }
+
+    Ok(SerializedBytes::from(UnsafeBytes::from(bytes)))
+}
+
+pub fn commit_state(commit: &Commit) -> ExternResult<SerializedBytes> {
+    match commit.state_chunks.is_empty() {
+        true => Ok(commit.state.clone()),
+        false => load_state(&commit.state_chunks),
+    }
+}
+
+pub fn document_initial_state(document: &Document) -> ExternResult<SerializedBytes> {
+    match document.state_chunks.is_empty() {
+        true => Ok(document.initial_state.clone()),
+        false => load_state(&document.state_chunks),
+    }
+}
+
+/// A commit or document record together with its full state, which for chunked
+/// records is not in the entry itself
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordWithState {
+    pub record: Record,
+    pub state: SerializedBytes,
+}
+
+pub fn assemble_commit_record(record: Record) -> ExternResult<RecordWithState> {
+    let commit = record
+        .entry()
+        .to_app_option::<Commit>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(format!(
+            "Record {} is not a commit",
+            record.action_address()
+        ))))?;
+    let state = commit_state(&commit)?;
+
+    Ok(RecordWithState { record, state })
+}
+
+pub fn assemble_document_record(record: Record) -> ExternResult<RecordWithState> {
+    let document = record
+        .entry()
+        .to_app_option::<Document>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?
+        .ok_or(wasm_error!(WasmErrorInner::Guest(format!(
+            "Record {} is not a document",
+            record.action_address()
+        ))))?;
+    let state = document_initial_state(&document)?;
+
+    Ok(RecordWithState { record, state })
+}
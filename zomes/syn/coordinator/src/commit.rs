@@ -1,10 +1,45 @@
 use hc_zome_syn_integrity::*;
 use hdk::prelude::*;
 
-use crate::{document::get_authors_for_document, utils::*};
+use crate::{
+    chunk::{assemble_commit_record, split_state, RecordWithState},
+    document::get_authors_for_document,
+    role::{get_workspace_role, GetWorkspaceRoleInput},
+    utils::*,
+    workspace::get_all_workspaces_for_document,
+};
+
+/// Fails if the document has workspaces and we are a viewer of all of them
+fn ensure_can_edit_document(document_hash: AnyDhtHash) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let workspace_hashes: Vec<EntryHash> = get_all_workspaces_for_document(document_hash)?
+        .into_iter()
+        .filter_map(|link| link.target.into_entry_hash())
+        .collect();
+    if workspace_hashes.is_empty() {
+        return Ok(());
+    }
+
+    for workspace_hash in workspace_hashes {
+        let role = get_workspace_role(GetWorkspaceRoleInput {
+            workspace_hash,
+            agent: my_pub_key.clone(),
+        })?;
+        if role != WorkspaceRole::Viewer {
+            return Ok(());
+        }
+    }
+
+    Err(wasm_error!("Viewers cannot commit to the document"))
+}
 
 #[hdk_extern]
-pub fn create_commit(commit: Commit) -> ExternResult<Record> {
+pub fn create_commit(mut commit: Commit) -> ExternResult<Record> {
+    ensure_can_edit_document(commit.document_hash.clone())?;
+    if commit.state_chunks.is_empty() {
+        (commit.state, commit.state_chunks) = split_state(commit.state)?;
+    }
+
     let c = EntryTypes::Commit(commit.clone());
     let action_hash = create_relaxed(c, commit.clone().try_into()?)?;
 
@@ -26,7 +61,7 @@ pub fn create_commit(commit: Commit) -> ExternResult<Record> {
         }
     }
 
-    let record = get(action_hash, GetOptions::default())?;
+    let record = get_commit(action_hash)?;
 
     record.ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
         "Could not get the record created just now"
@@ -56,6 +91,14 @@ pub fn get_commit(commit_hash: ActionHash) -> ExternResult<Option<Record>> {
     get(commit_hash, GetOptions::default())
 }
 
+/// The commit with its state loaded from the chunks if it was split
+#[hdk_extern]
+pub fn get_commit_with_state(commit_hash: ActionHash) -> ExternResult<Option<RecordWithState>> {
+    get_commit(commit_hash)?
+        .map(assemble_commit_record)
+        .transpose()
+}
+
 #[hdk_extern]
 pub fn get_commits_for_document(document_hash: AnyDhtHash) -> ExternResult<Vec<Link>> {
     get_links(
@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{Read, Write},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{
+    messages::{MessagePayload, SessionMessage},
+    utils::create_relaxed,
+};
+
+/// Payloads bigger than this once serialized are deflated
+pub const COMPRESSION_THRESHOLD: usize = 8 * 1024;
+
+/// Payloads still bigger than this are split into this many bytes per `MessagePayload::Fragment`
+pub const FRAGMENT_SIZE: usize = 64 * 1024;
+
+fn encode_payload(payload: &MessagePayload) -> ExternResult<Vec<u8>> {
+    Ok(ExternIO::encode(payload).map_err(|err| wasm_error!(err))?.0)
+}
+
+fn decode_payload(bytes: Vec<u8>) -> ExternResult<MessagePayload> {
+    ExternIO(bytes).decode().map_err(|err| wasm_error!(err))
+}
+
+/// Wraps big payloads in `MessagePayload::Compressed`
+pub fn compress_session_message(mut message: SessionMessage) -> ExternResult<SessionMessage> {
+    let bytes = encode_payload(&message.payload)?;
+    if bytes.len() <= COMPRESSION_THRESHOLD {
+        return Ok(message);
+    }
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&bytes)
+        .map_err(|err| wasm_error!(WasmErrorInner::Guest(err.to_string())))?;
+    let compressed = encoder
+        .finish()
+        .map_err(|err| wasm_error!(WasmErrorInner::Guest(err.to_string())))?;
+
+    message.payload = MessagePayload::Compressed {
+        data: SerializedBytes::from(UnsafeBytes::from(compressed)),
+    };
+    Ok(message)
+}
+
+pub fn decompress_session_message(mut message: SessionMessage) -> ExternResult<SessionMessage> {
+    let MessagePayload::Compressed { data } = &message.payload else {
+        return Ok(message);
+    };
+
+    let mut bytes = Vec::new();
+    DeflateDecoder::new(data.bytes().as_slice())
+        .read_to_end(&mut bytes)
+        .map_err(|err| wasm_error!(WasmErrorInner::Guest(err.to_string())))?;
+
+    message.payload = decode_payload(bytes)?;
+    Ok(message)
+}
+
+/// Splits messages whose payload is bigger than `FRAGMENT_SIZE` into numbered fragments,
+/// every fragment keeping the message's workspace and sequence
+pub fn fragment_session_message(message: SessionMessage) -> ExternResult<Vec<SessionMessage>> {
+    let bytes = encode_payload(&message.payload)?;
+    if bytes.len() <= FRAGMENT_SIZE {
+        return Ok(vec![message]);
+    }
+
+    let message_id = random_bytes(16)?.to_vec();
+    let chunks: Vec<&[u8]> = bytes.chunks(FRAGMENT_SIZE).collect();
+    let total = chunks.len() as u32;
+
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| SessionMessage {
+            workspace_hash: message.workspace_hash.clone(),
+            sequence: message.sequence,
+            payload: MessagePayload::Fragment {
+                message_id: message_id.clone(),
+                index: index as u32,
+                total,
+                data: SerializedBytes::from(UnsafeBytes::from(chunk.to_vec())),
+            },
+        })
+        .collect())
+}
+
+/// Fragments received this long ago are dropped along with their incomplete message
+pub const FRAGMENT_TTL_MICROS: i64 = 60 * 1_000_000;
+
+/// Stored fragments that weren't deleted yet, with their action hashes
+fn stored_fragments() -> ExternResult<Vec<(ActionHash, MessageFragment)>> {
+    let deleted: HashSet<ActionHash> = query(
+        ChainQueryFilter::new()
+            .action_type(ActionType::Delete)
+            .include_entries(false),
+    )?
+    .into_iter()
+    .filter_map(|record| match record.action() {
+        Action::Delete(delete) => Some(delete.deletes_address.clone()),
+        _ => None,
+    })
+    .collect();
+
+    Ok(query(
+        ChainQueryFilter::new()
+            .entry_type(UnitEntryTypes::MessageFragment.try_into()?)
+            .include_entries(true),
+    )?
+    .into_iter()
+    .filter(|record| !deleted.contains(record.action_address()))
+    .filter_map(|record| {
+        let fragment = record.entry().to_app_option::<MessageFragment>().ok()??;
+        Some((record.action_address().clone(), fragment))
+    })
+    .collect())
+}
+
+fn delete_fragment(action_hash: ActionHash) -> ExternResult<()> {
+    delete_entry(DeleteInput::new(action_hash, ChainTopOrdering::Relaxed))?;
+    Ok(())
+}
+
+/// Stores fragments as private entries until all of them arrived, every fragment comes in its
+/// own zome call. Returns the reassembled message once the last one comes in, `None` while
+/// some are missing or if the fragment is inconsistent with the ones already received.
+pub fn reassemble_session_message(
+    message: SessionMessage,
+    sender: &AgentPubKey,
+) -> ExternResult<Option<SessionMessage>> {
+    let MessagePayload::Fragment {
+        message_id,
+        index,
+        total,
+        data,
+    } = message.payload
+    else {
+        return Ok(Some(message));
+    };
+    if index >= total {
+        warn!("Dropping fragment {} of {} from {}, out of range", index, total, sender);
+        return Ok(None);
+    }
+    let now = sys_time()?;
+
+    let mut fragments = Vec::new();
+    for (action_hash, fragment) in stored_fragments()? {
+        if now.as_micros() - fragment.received_at.as_micros() > FRAGMENT_TTL_MICROS {
+            delete_fragment(action_hash)?;
+        } else if fragment.sender.eq(sender) && fragment.message_id == message_id {
+            fragments.push((action_hash, fragment));
+        }
+    }
+    if fragments.iter().any(|(_, fragment)| fragment.total != total) {
+        warn!(
+            "Dropping fragment from {}, which doesn't match the total of the message's other fragments",
+            sender
+        );
+        return Ok(None);
+    }
+
+    if !fragments.iter().any(|(_, fragment)| fragment.index == index) {
+        let fragment = MessageFragment {
+            sender: sender.clone(),
+            message_id,
+            index,
+            total,
+            received_at: now,
+            data,
+        };
+        let action_hash = create_relaxed(
+            EntryTypes::MessageFragment(fragment.clone()),
+            fragment.clone().try_into()?,
+        )?;
+        fragments.push((action_hash, fragment));
+    }
+
+    let received: BTreeMap<u32, &SerializedBytes> = fragments
+        .iter()
+        .map(|(_, fragment)| (fragment.index, &fragment.data))
+        .collect();
+    if received.len() < total as usize {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    for data in received.values() {
+        bytes.extend_from_slice(data.bytes());
+    }
+    for (action_hash, _) in fragments {
+        delete_fragment(action_hash)?;
+    }
+
+    Ok(Some(SessionMessage {
+        workspace_hash: message.workspace_hash,
+        sequence: message.sequence,
+        payload: decode_payload(bytes)?,
+    }))
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::cart::{find_agent_cart, replace_agent_cart};
+
+fn delivery_slots_path() -> Path {
+    Path::from("delivery_slots")
+}
+
+#[hdk_extern]
+pub fn create_delivery_slot(slot: DeliverySlot) -> ExternResult<Record> {
+    let slot_hash = create_entry(EntryTypes::DeliverySlot(slot))?;
+    create_link(
+        delivery_slots_path().path_entry_hash()?,
+        slot_hash.clone(),
+        LinkTypes::DeliverySlots,
+        (),
+    )?;
+
+    get(slot_hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AvailableSlot {
+    pub slot_hash: ActionHash,
+    pub slot: DeliverySlot,
+    pub remaining: u32,
+}
+
+/// Slots that haven't ended yet and still have room for another reservation
+#[hdk_extern]
+pub fn get_available_slots(_: ()) -> ExternResult<Vec<AvailableSlot>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(
+            delivery_slots_path().path_entry_hash()?,
+            LinkTypes::DeliverySlots,
+        )?
+        .build(),
+    )?;
+    let now = sys_time()?;
+
+    let mut slots = Vec::new();
+    for link in links {
+        let Some(slot_hash) = link.target.into_action_hash() else {
+            continue;
+        };
+        let Some(slot) = get_delivery_slot(slot_hash.clone())? else {
+            continue;
+        };
+        if slot.ends_at <= now {
+            continue;
+        }
+
+        let reserved = get_slot_reservations(slot_hash.clone())?.len() as u32;
+        if reserved < slot.capacity {
+            slots.push(AvailableSlot {
+                slot_hash,
+                remaining: slot.capacity - reserved,
+                slot,
+            });
+        }
+    }
+    slots.sort_by_key(|available| available.slot.starts_at);
+
+    Ok(slots)
+}
+
+fn get_delivery_slot(slot_hash: ActionHash) -> ExternResult<Option<DeliverySlot>> {
+    let Some(record) = get(slot_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+
+    record
+        .entry()
+        .to_app_option::<DeliverySlot>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))
+}
+
+/// Reservations holding a place of the slot by index: the oldest link for each place,
+/// later ones for the same place lost a race for it
+fn get_slot_reservations(
+    slot_hash: ActionHash,
+) -> ExternResult<BTreeMap<u32, (Link, ReservationTag)>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(slot_hash, LinkTypes::SlotToReservations)?.build(),
+    )?;
+
+    let mut reservations: BTreeMap<u32, (Link, ReservationTag)> = BTreeMap::new();
+    for link in links {
+        let Ok(tag) = ReservationTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            link.tag.clone().into_inner(),
+        ))) else {
+            continue;
+        };
+        let is_older = match reservations.get(&tag.index) {
+            Some((holder, _)) => {
+                (link.timestamp, link.create_link_hash.to_string())
+                    < (holder.timestamp, holder.create_link_hash.to_string())
+            }
+            None => true,
+        };
+        if is_older {
+            reservations.insert(tag.index, (link, tag));
+        }
+    }
+
+    Ok(reservations)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReserveDeliverySlotInput {
+    pub cart_id: String,
+    pub slot_hash: ActionHash,
+}
+
+#[hdk_extern]
+pub fn reserve_delivery_slot(input: ReserveDeliverySlotInput) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&input.cart_id)?
+        .ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can reserve a delivery slot"));
+    }
+    if cart.delivery_slot.as_ref() == Some(&input.slot_hash) {
+        return Err(wasm_error!("Cart already has this delivery slot"));
+    }
+
+    let slot = get_delivery_slot(input.slot_hash.clone())?
+        .ok_or(wasm_error!("Delivery slot not found"))?;
+    if slot.ends_at <= sys_time()? {
+        return Err(wasm_error!("Delivery slot has already ended"));
+    }
+
+    // Somebody may take the same place concurrently, then we try the next free one
+    loop {
+        let reservations = get_slot_reservations(input.slot_hash.clone())?;
+        let index = (0..slot.capacity)
+            .find(|index| !reservations.contains_key(index))
+            .ok_or(wasm_error!("Delivery slot is full"))?;
+
+        let reservation_hash = create_link(
+            input.slot_hash.clone(),
+            agent_info()?.agent_initial_pubkey,
+            LinkTypes::SlotToReservations,
+            SerializedBytes::try_from(ReservationTag {
+                index,
+                cart_id: input.cart_id.clone(),
+            })
+            .map_err(|err| wasm_error!(err))?
+            .bytes()
+            .clone(),
+        )?;
+
+        let holder = get_slot_reservations(input.slot_hash.clone())?.remove(&index);
+        match holder {
+            Some((link, _)) if !link.create_link_hash.eq(&reservation_hash) => {
+                delete_link(reservation_hash)?;
+            }
+            _ => break,
+        }
+    }
+
+    if let Some(previous_slot) = cart.delivery_slot.replace(input.slot_hash) {
+        delete_slot_reservation(previous_slot, &input.cart_id)?;
+    }
+    let hash = replace_agent_cart(cart_link, cart)?;
+
+    get(hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+#[hdk_extern]
+pub fn cancel_delivery_slot_reservation(cart_id: String) -> ExternResult<Record> {
+    let (cart_link, mut cart) = find_agent_cart(&cart_id)?.ok_or(wasm_error!("Cart not found"))?;
+
+    if cart.status != CartStatus::Active {
+        return Err(wasm_error!("Only active carts can cancel their delivery slot"));
+    }
+
+    let slot_hash = cart
+        .delivery_slot
+        .take()
+        .ok_or(wasm_error!("Cart has no delivery slot"))?;
+    delete_slot_reservation(slot_hash, &cart_id)?;
+    let hash = replace_agent_cart(cart_link, cart)?;
+
+    get(hash, GetOptions::default())?
+        .ok_or(wasm_error!("Could not get the record created just now"))
+}
+
+/// Whether one of the slot's places is still held by the caller for the cart. Reservations
+/// only settle once gossiped, so a place we took may turn out to be taken earlier by someone else.
+pub fn holds_slot_reservation(slot_hash: ActionHash, cart_id: &str) -> ExternResult<bool> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    Ok(get_slot_reservations(slot_hash)?
+        .into_values()
+        .any(|(link, tag)| link.author.eq(&my_pub_key) && tag.cart_id == cart_id))
+}
+
+fn delete_slot_reservation(slot_hash: ActionHash, cart_id: &str) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    for (_, (link, tag)) in get_slot_reservations(slot_hash)? {
+        if link.author.eq(&my_pub_key) && tag.cart_id == cart_id {
+            delete_link(link.create_link_hash)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,14 @@
 use hc_zome_syn_integrity::*;
 use hdk::prelude::*;
 
+use crate::chunk::{assemble_document_record, split_state, RecordWithState};
+
 #[hdk_extern]
-pub fn create_document(document: Document) -> ExternResult<Record> {
+pub fn create_document(mut document: Document) -> ExternResult<Record> {
+    if document.state_chunks.is_empty() {
+        (document.initial_state, document.state_chunks) = split_state(document.initial_state)?;
+    }
+
     let document_hash = create_entry(EntryTypes::Document(document.clone()))?;
     create_link(
         document_hash.clone(),
@@ -11,7 +17,7 @@ pub fn create_document(document: Document) -> ExternResult<Record> {
         (),
     )?;
 
-    let maybe_record = get(document_hash, GetOptions::default())?;
+    let maybe_record = get_document(document_hash.into())?;
     let record = maybe_record.ok_or(wasm_error!(WasmErrorInner::Guest(String::from(
         "Could not get the record created just now"
     ))))?;
@@ -24,6 +30,16 @@ pub fn get_document(document_hash: AnyDhtHash) -> ExternResult<Option<Record>> {
     get(document_hash, GetOptions::default())
 }
 
+/// The document with its initial state loaded from the chunks if it was split
+#[hdk_extern]
+pub fn get_document_with_state(
+    document_hash: AnyDhtHash,
+) -> ExternResult<Option<RecordWithState>> {
+    get_document(document_hash)?
+        .map(assemble_document_record)
+        .transpose()
+}
+
 #[hdk_extern]
 pub fn get_authors_for_document(document_hash: AnyDhtHash) -> ExternResult<Vec<Link>> {
     get_links(GetLinksInputBuilder::try_new(document_hash, LinkTypes::DocumentToAuthors)?.build())
@@ -0,0 +1,253 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{
+    messages::{send_message, MessagePayload, SendMessageInput, SessionMessage},
+    role::has_workspace_access,
+    scribe::get_session_scribe,
+    utils::{create_link_relaxed, create_relaxed},
+    workspace::{get_workspace_entry, get_workspace_session_participants, is_workspace_member},
+};
+
+/// The X25519 key the agent published for the workspace, newest first if there are several
+pub fn get_encryption_key(
+    workspace_hash: EntryHash,
+    agent: &AgentPubKey,
+) -> ExternResult<Option<X25519PubKey>> {
+    let mut links = get_links(
+        GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToEncryptionKey)?
+            .build(),
+    )?;
+    links.sort_by_key(|link| link.timestamp);
+
+    Ok(links
+        .into_iter()
+        .rev()
+        .filter(|link| link.author.eq(agent))
+        .find_map(|link| {
+            EncryptionKeyTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+                link.tag.into_inner(),
+            )))
+            .ok()
+        })
+        .map(|tag| tag.x25519_pub_key))
+}
+
+/// Our X25519 key for the workspace, created and published the first time it's needed
+pub fn ensure_encryption_key(workspace_hash: EntryHash) -> ExternResult<X25519PubKey> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    if let Some(key) = get_encryption_key(workspace_hash.clone(), &my_pub_key)? {
+        return Ok(key);
+    }
+
+    let x25519_pub_key = create_x25519_keypair()?;
+    create_link_relaxed(
+        workspace_hash,
+        my_pub_key,
+        LinkTypes::WorkspaceToEncryptionKey,
+        SerializedBytes::try_from(EncryptionKeyTag {
+            x25519_pub_key: x25519_pub_key.clone(),
+        })
+        .map_err(|err| wasm_error!(err))?
+        .bytes()
+        .clone(),
+    )?;
+
+    Ok(x25519_pub_key)
+}
+
+/// Our session keys for the workspace, newest first
+fn get_session_keys(workspace_hash: &EntryHash) -> ExternResult<Vec<XSalsa20Poly1305KeyRef>> {
+    let records = query(
+        ChainQueryFilter::new()
+            .entry_type(UnitEntryTypes::SessionKey.try_into()?)
+            .include_entries(true),
+    )?;
+
+    let mut keys = Vec::new();
+    for record in records.into_iter().rev() {
+        if let Ok(Some(session_key)) = record.entry().to_app_option::<SessionKey>() {
+            if session_key.workspace_hash.eq(workspace_hash) {
+                keys.push(session_key.key_ref);
+            }
+        }
+    }
+
+    Ok(keys)
+}
+
+fn save_session_key(workspace_hash: EntryHash, key_ref: XSalsa20Poly1305KeyRef) -> ExternResult<()> {
+    let session_key = SessionKey {
+        workspace_hash,
+        key_ref,
+    };
+    create_relaxed(
+        EntryTypes::SessionKey(session_key.clone()),
+        session_key.try_into()?,
+    )?;
+
+    Ok(())
+}
+
+/// Creates a new session key for an encrypted workspace and shares it with every
+/// session participant that published an encryption key
+#[hdk_extern]
+pub fn rotate_session_key(workspace_hash: EntryHash) -> ExternResult<()> {
+    if !get_workspace_entry(workspace_hash.clone())?.encrypted {
+        return Err(wasm_error!("Workspace is not encrypted"));
+    }
+    ensure_encryption_key(workspace_hash.clone())?;
+
+    let key_ref = x_salsa20_poly1305_shared_secret_create_random(None)?;
+    save_session_key(workspace_hash.clone(), key_ref)?;
+
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let participants: Vec<AgentPubKey> =
+        get_workspace_session_participants(workspace_hash.clone())?
+            .into_iter()
+            .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+            .filter(|agent| !agent.eq(&my_pub_key))
+            .collect();
+    share_session_key(workspace_hash, participants)
+}
+
+/// Sends our newest session key to each recipient that is a member of the workspace,
+/// encrypted for the key they published
+pub fn share_session_key(
+    workspace_hash: EntryHash,
+    recipients: Vec<AgentPubKey>,
+) -> ExternResult<()> {
+    let Some(key_ref) = get_session_keys(&workspace_hash)?.into_iter().next() else {
+        return Ok(());
+    };
+    let my_key = ensure_encryption_key(workspace_hash.clone())?;
+
+    for recipient in recipients {
+        if !is_workspace_member(workspace_hash.clone(), &recipient)?
+            || !has_workspace_access(workspace_hash.clone(), &recipient)?
+        {
+            warn!("Not sharing the session key with {}, who is not a member", recipient);
+            continue;
+        }
+        let Some(their_key) = get_encryption_key(workspace_hash.clone(), &recipient)? else {
+            warn!("{} has not published an encryption key yet", recipient);
+            continue;
+        };
+        let encrypted_key =
+            x_salsa20_poly1305_shared_secret_export(my_key.clone(), their_key, key_ref.clone())?;
+
+        send_message(SendMessageInput {
+            message: SessionMessage {
+                workspace_hash: workspace_hash.clone(),
+                sequence: None,
+                payload: MessagePayload::SessionKey {
+                    sender_key: my_key.clone(),
+                    encrypted_key,
+                },
+            },
+            recipients: vec![recipient],
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Keeps a session key another participant shared with us, if it comes from the key they published
+pub fn ingest_session_key(
+    workspace_hash: EntryHash,
+    sender: &AgentPubKey,
+    sender_key: X25519PubKey,
+    encrypted_key: XSalsa20Poly1305EncryptedData,
+) -> ExternResult<()> {
+    if get_encryption_key(workspace_hash.clone(), sender)?.as_ref() != Some(&sender_key) {
+        return Err(wasm_error!("Session key was not sent with the sender's published key"));
+    }
+    let my_key = get_encryption_key(workspace_hash.clone(), &agent_info()?.agent_initial_pubkey)?
+        .ok_or(wasm_error!("We have not published an encryption key for this workspace"))?;
+
+    let key_ref =
+        x_salsa20_poly1305_shared_secret_ingest(my_key, sender_key, encrypted_key, None)?;
+    save_session_key(workspace_hash, key_ref)
+}
+
+/// Called when somebody joins or asks for the key: the scribe shares it,
+/// or anyone holding it while there is no scribe
+pub fn offer_session_key(workspace_hash: EntryHash, agent: AgentPubKey) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let is_keeper = match get_session_scribe(workspace_hash.clone())? {
+        Some(scribe) => scribe.scribe.eq(&my_pub_key),
+        None => true,
+    };
+
+    if is_keeper {
+        share_session_key(workspace_hash, vec![agent])?;
+    }
+    Ok(())
+}
+
+/// Payloads that carry document state or app data and are encrypted in encrypted workspaces
+fn is_private_payload(payload: &MessagePayload) -> bool {
+    matches!(
+        payload,
+        MessagePayload::ChangeNotice { .. }
+            | MessagePayload::NewCommit { .. }
+            | MessagePayload::SyncReq { .. }
+            | MessagePayload::App { .. }
+            | MessagePayload::Compressed { .. }
+    )
+}
+
+/// Wraps the payload in `MessagePayload::Encrypted` if the workspace is encrypted and the
+/// payload carries document state. Fails rather than sending that state in the clear.
+pub fn encrypt_session_message(mut message: SessionMessage) -> ExternResult<SessionMessage> {
+    if !is_private_payload(&message.payload)
+        || !get_workspace_entry(message.workspace_hash.clone())?.encrypted
+    {
+        return Ok(message);
+    }
+
+    let key_ref = get_session_keys(&message.workspace_hash)?
+        .into_iter()
+        .next()
+        .ok_or(wasm_error!("No session key for this encrypted workspace yet"))?;
+    let bytes = ExternIO::encode(&message.payload).map_err(|err| wasm_error!(err))?;
+    let encrypted_payload =
+        x_salsa20_poly1305_encrypt(key_ref, XSalsa20Poly1305Data::from(bytes.0))?;
+
+    message.payload = MessagePayload::Encrypted { encrypted_payload };
+    Ok(message)
+}
+
+/// Unwraps `MessagePayload::Encrypted` with any of our keys for the workspace.
+/// `None` if we can't decrypt it, in which case we ask the sender for the key.
+pub fn decrypt_session_message(
+    mut message: SessionMessage,
+    sender: &AgentPubKey,
+) -> ExternResult<Option<SessionMessage>> {
+    let MessagePayload::Encrypted { encrypted_payload } = &message.payload else {
+        return Ok(Some(message));
+    };
+
+    for key_ref in get_session_keys(&message.workspace_hash)? {
+        let Some(data) = x_salsa20_poly1305_decrypt(key_ref, encrypted_payload.clone())? else {
+            continue;
+        };
+        let payload: MessagePayload = ExternIO(data.as_ref().to_vec())
+            .decode()
+            .map_err(|err| wasm_error!(err))?;
+
+        message.payload = payload;
+        return Ok(Some(message));
+    }
+
+    ensure_encryption_key(message.workspace_hash.clone())?;
+    send_message(SendMessageInput {
+        message: SessionMessage {
+            workspace_hash: message.workspace_hash,
+            sequence: None,
+            payload: MessagePayload::SessionKeyReq,
+        },
+        recipients: vec![sender.clone()],
+    })?;
+    Ok(None)
+}
@@ -0,0 +1,299 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+use itertools::Itertools;
+
+pub fn get_commit_entry(commit_hash: ActionHash) -> ExternResult<Option<(Record, Commit)>> {
+    let Some(record) = get(commit_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+    let commit = record
+        .entry()
+        .to_app_option::<Commit>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?;
+
+    Ok(commit.map(|commit| (record, commit)))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitHistoryEntry {
+    pub commit_hash: ActionHash,
+    /// Agent that created the commit entry, usually the scribe
+    pub author: AgentPubKey,
+    pub timestamp: Timestamp,
+    pub commit: Commit,
+}
+
+/// Where a page of the history left off: the commits to list next, all of whose known children
+/// have been listed, each with its generation counted from the first commit
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitHistoryCursor {
+    pub frontier: Vec<(ActionHash, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetCommitHistoryInput {
+    pub commit_hash: ActionHash,
+    /// How many generations of ancestors to walk, the whole history if not given
+    #[serde(default)]
+    pub depth: Option<usize>,
+    /// `next_cursor` of the previous page, the first page starts at `commit_hash`
+    #[serde(default)]
+    pub cursor: Option<CommitHistoryCursor>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CommitHistory {
+    pub commits: Vec<CommitHistoryEntry>,
+    /// `None` once there is nothing older left to list
+    pub next_cursor: Option<CommitHistoryCursor>,
+}
+
+/// Lists the commits reachable from `commit_hash` newest first, breaking ties by hash so every
+/// client gets the same order. As commits are created after their previous commits, every commit
+/// comes before its ancestors. Each page only fetches the commits it lists and their parents.
+#[hdk_extern]
+pub fn get_commit_history(input: GetCommitHistoryInput) -> ExternResult<CommitHistory> {
+    let frontier = match input.cursor {
+        Some(cursor) => cursor.frontier,
+        None => vec![(input.commit_hash, 0)],
+    };
+
+    let mut pending: HashMap<ActionHash, (CommitHistoryEntry, usize)> = HashMap::new();
+    for (hash, generation) in frontier {
+        add_to_frontier(&mut pending, hash, generation)?;
+    }
+
+    let mut commits = Vec::new();
+    while commits.len() < input.limit {
+        let Some(newest) = pending
+            .iter()
+            .max_by(|(a_hash, (a, _)), (b_hash, (b, _))| {
+                a.timestamp
+                    .cmp(&b.timestamp)
+                    .then_with(|| a_hash.to_string().cmp(&b_hash.to_string()))
+            })
+            .map(|(hash, _)| hash.clone())
+        else {
+            break;
+        };
+        let Some((entry, generation)) = pending.remove(&newest) else {
+            break;
+        };
+
+        let within_depth = match input.depth {
+            Some(depth) => generation < depth,
+            None => true,
+        };
+        if within_depth {
+            for previous in entry.commit.previous_commit_hashes.iter().unique() {
+                add_to_frontier(&mut pending, previous.clone(), generation + 1)?;
+            }
+        }
+        commits.push(entry);
+    }
+
+    let next_cursor = if pending.is_empty() {
+        None
+    } else {
+        Some(CommitHistoryCursor {
+            frontier: pending
+                .into_iter()
+                .map(|(hash, (_, generation))| (hash, generation))
+                .collect(),
+        })
+    };
+
+    Ok(CommitHistory {
+        commits,
+        next_cursor,
+    })
+}
+
+fn add_to_frontier(
+    pending: &mut HashMap<ActionHash, (CommitHistoryEntry, usize)>,
+    hash: ActionHash,
+    generation: usize,
+) -> ExternResult<()> {
+    if let Some((_, known_generation)) = pending.get_mut(&hash) {
+        *known_generation = (*known_generation).min(generation);
+        return Ok(());
+    }
+    let Some((record, commit)) = get_commit_entry(hash.clone())? else {
+        warn!("Commit {} not found while walking history", hash);
+        return Ok(());
+    };
+
+    pending.insert(
+        hash.clone(),
+        (
+            CommitHistoryEntry {
+                commit_hash: hash,
+                author: record.action().author().clone(),
+                timestamp: record.action().timestamp(),
+                commit,
+            },
+            generation,
+        ),
+    );
+    Ok(())
+}
+
+/// Lazily fetched view of the commit DAG, only timestamps and parents
+#[derive(Default)]
+pub struct CommitGraph {
+    nodes: HashMap<ActionHash, (Timestamp, Vec<ActionHash>)>,
+}
+
+impl CommitGraph {
+    fn node(&mut self, commit_hash: &ActionHash) -> ExternResult<(Timestamp, Vec<ActionHash>)> {
+        if let Some(node) = self.nodes.get(commit_hash) {
+            return Ok(node.clone());
+        }
+        let (record, commit) = get_commit_entry(commit_hash.clone())?.ok_or(wasm_error!(
+            WasmErrorInner::Guest(format!("Commit {} not found", commit_hash))
+        ))?;
+        let node = (record.action().timestamp(), commit.previous_commit_hashes);
+        self.nodes.insert(commit_hash.clone(), node.clone());
+        Ok(node)
+    }
+
+    /// Whether `ancestor` is reachable from `descendant`, a commit counts as its own ancestor
+    pub fn is_ancestor(
+        &mut self,
+        ancestor: &ActionHash,
+        descendant: &ActionHash,
+    ) -> ExternResult<bool> {
+        let mut visited: HashSet<ActionHash> = HashSet::new();
+        let mut queue: VecDeque<ActionHash> = VecDeque::from([descendant.clone()]);
+
+        while let Some(hash) = queue.pop_front() {
+            if hash.eq(ancestor) {
+                return Ok(true);
+            }
+            if !visited.insert(hash.clone()) {
+                continue;
+            }
+            queue.extend(self.node(&hash)?.1);
+        }
+
+        Ok(false)
+    }
+
+    /// Best common ancestors of both commits: common ancestors that aren't ancestors of
+    /// another common ancestor. Walks newest first and stops as soon as only commits
+    /// below an already found common ancestor are left.
+    pub fn merge_bases(
+        &mut self,
+        commit_a: &ActionHash,
+        commit_b: &ActionHash,
+    ) -> ExternResult<Vec<ActionHash>> {
+        const FROM_A: u8 = 1;
+        const FROM_B: u8 = 2;
+        const STALE: u8 = 4;
+
+        if commit_a.eq(commit_b) {
+            return Ok(vec![commit_a.clone()]);
+        }
+
+        let mut flags: HashMap<ActionHash, u8> = HashMap::new();
+        flags.insert(commit_a.clone(), FROM_A);
+        flags.insert(commit_b.clone(), FROM_B);
+        let mut queue: Vec<ActionHash> = vec![commit_a.clone(), commit_b.clone()];
+        let mut candidates: Vec<ActionHash> = Vec::new();
+
+        while queue.iter().any(|hash| flags[hash] & STALE == 0) {
+            let mut newest = (0, self.node(&queue[0])?.0);
+            for (i, hash) in queue.iter().enumerate().skip(1) {
+                let timestamp = self.node(hash)?.0;
+                if timestamp > newest.1 {
+                    newest = (i, timestamp);
+                }
+            }
+            let hash = queue.swap_remove(newest.0);
+
+            let mut hash_flags = flags[&hash];
+            if hash_flags & (FROM_A | FROM_B) == FROM_A | FROM_B && hash_flags & STALE == 0 {
+                candidates.push(hash.clone());
+                hash_flags |= STALE;
+                flags.insert(hash.clone(), hash_flags);
+            }
+
+            for previous in self.node(&hash)?.1 {
+                let previous_flags = flags.entry(previous.clone()).or_insert(0);
+                if *previous_flags & hash_flags == hash_flags {
+                    continue;
+                }
+                *previous_flags |= hash_flags;
+                queue.push(previous);
+            }
+        }
+
+        let mut bases = Vec::new();
+        for candidate in candidates.iter() {
+            let mut redundant = false;
+            for other in candidates.iter().filter(|other| other.ne(&candidate)) {
+                if self.is_ancestor(candidate, other)? {
+                    redundant = true;
+                    break;
+                }
+            }
+            if !redundant {
+                bases.push(candidate.clone());
+            }
+        }
+
+        Ok(bases)
+    }
+
+    /// The newest of the best common ancestors, `None` if the commits share no history
+    pub fn merge_base(
+        &mut self,
+        commit_a: &ActionHash,
+        commit_b: &ActionHash,
+    ) -> ExternResult<Option<ActionHash>> {
+        let mut newest: Option<(Timestamp, ActionHash)> = None;
+        for base in self.merge_bases(commit_a, commit_b)? {
+            let (timestamp, _) = self.node(&base)?;
+            let is_newer = match &newest {
+                Some((newest_timestamp, newest_hash)) => (timestamp, base.to_string())
+                    > (*newest_timestamp, newest_hash.to_string()),
+                None => true,
+            };
+            if is_newer {
+                newest = Some((timestamp, base));
+            }
+        }
+
+        Ok(newest.map(|(_, hash)| hash))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FindMergeBaseInput {
+    pub commit_a: ActionHash,
+    pub commit_b: ActionHash,
+}
+
+#[hdk_extern]
+pub fn find_merge_base(input: FindMergeBaseInput) -> ExternResult<Option<ActionHash>> {
+    CommitGraph::default().merge_base(&input.commit_a, &input.commit_b)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IsAncestorInput {
+    pub ancestor: ActionHash,
+    pub descendant: ActionHash,
+}
+
+#[hdk_extern]
+pub fn is_ancestor(input: IsAncestorInput) -> ExternResult<bool> {
+    CommitGraph::default().is_ancestor(&input.ancestor, &input.descendant)
+}
@@ -1,22 +1,52 @@
 use hc_zome_syn_integrity::*;
 use hdk::prelude::*;
 
+mod analytics;
+mod attestation;
+mod catalog;
+mod chunk;
 mod commit;
+mod compression;
 mod document;
+mod encryption;
+mod history;
+mod merge;
 mod messages;
+mod recording;
+mod role;
+mod scribe;
+mod snapshot;
 mod tags;
 mod utils;
 mod workspace;
 mod cart;  // Add this!
+mod cart_export;
+mod checkout;
+mod delivery;
 
 // Add all pub use statements
+pub use analytics::*;
+pub use attestation::*;
+pub use catalog::*;
+pub use chunk::*;
 pub use commit::*;
+pub use compression::*;
 pub use document::*;
+pub use encryption::*;
+pub use history::*;
+pub use merge::*;
 pub use messages::*;
+pub use recording::*;
+pub use role::*;
+pub use scribe::*;
+pub use snapshot::*;
 pub use tags::*;
 pub use utils::*;
 pub use workspace::*;
 pub use cart::*; 
+pub use cart_export::*;
+pub use checkout::*;
+pub use delivery::*;
 
 #[hdk_extern]
 pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
@@ -33,9 +63,100 @@ pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
 }
 
 #[hdk_extern]
-pub fn recv_remote_signal(message: SessionMessage) -> ExternResult<()> {
+pub fn recv_remote_signal(message: RemoteMessage) -> ExternResult<()> {
+    match message {
+        RemoteMessage::Session(message) => recv_session_message(message),
+        // Addressed to us explicitly, no workspace to check membership against
+        RemoteMessage::Direct(message) => emit_signal(Signal::DirectMessage {
+            provenance: call_info()?.provenance,
+            message,
+        }),
+    }
+}
+
+fn recv_session_message(message: SessionMessage) -> ExternResult<()> {
     let info = call_info()?;
 
+    // Anyone on the network can call us, only listen to the workspace's own people.
+    // Joiners may not have their participant link gossiped to us yet, and asking for the
+    // session key is gated on membership by the key keeper, see `share_session_key`.
+    let is_open_to_anyone = matches!(
+        message.payload,
+        MessagePayload::JoinSession | MessagePayload::SessionKeyReq
+    );
+    let is_member = is_workspace_member(message.workspace_hash.clone(), &info.provenance)?;
+    if !is_open_to_anyone && !is_member {
+        warn!(
+            "Dropping session message from {}, who is not a member of workspace {}",
+            info.provenance, message.workspace_hash
+        );
+        return Ok(());
+    }
+    let Some(message) = reassemble_session_message(message, &info.provenance)? else {
+        // Waiting for the rest of the fragments
+        return Ok(());
+    };
+    let Some(message) = decrypt_session_message(message, &info.provenance)? else {
+        warn!("Dropping encrypted session message from {} we have no key for", info.provenance);
+        return Ok(());
+    };
+    let message = decompress_session_message(message)?;
+
+    record_session_message(&message, &info.provenance)?;
+
+    match &message.payload {
+        MessagePayload::CommitAttestation {
+            commit_hash,
+            heads,
+            signature,
+        } => {
+            if let Err(err) = add_commit_attestation(
+                commit_hash.clone(),
+                info.provenance.clone(),
+                heads.clone(),
+                signature.clone(),
+            ) {
+                warn!("Ignoring attestation from {}: {:?}", info.provenance, err);
+            }
+        }
+        MessagePayload::ScribeElection { scribe, term }
+            if scribe.eq(&agent_info()?.agent_initial_pubkey) =>
+        {
+            if let Err(err) =
+                accept_scribe_election(message.workspace_hash.clone(), &info.provenance, *term)
+            {
+                warn!("Ignoring scribe election from {}: {:?}", info.provenance, err);
+            }
+        }
+        MessagePayload::SessionKey {
+            sender_key,
+            encrypted_key,
+        } => {
+            if let Err(err) = ingest_session_key(
+                message.workspace_hash.clone(),
+                &info.provenance,
+                sender_key.clone(),
+                encrypted_key.clone(),
+            ) {
+                warn!("Ignoring session key from {}: {:?}", info.provenance, err);
+            }
+        }
+        MessagePayload::JoinSession | MessagePayload::SessionKeyReq => {
+            if let Err(err) =
+                offer_session_key(message.workspace_hash.clone(), info.provenance.clone())
+            {
+                warn!("Could not share the session key with {}: {:?}", info.provenance, err);
+            }
+        }
+        _ => {}
+    }
+
+    // The UI only sees joiners once their membership reached us, until then the
+    // session store discovers them through their participant link
+    if !is_member {
+        return Ok(());
+    }
+
     let notice = Signal::SessionMessage {
         message,
         provenance: info.provenance,
@@ -51,6 +172,10 @@ pub enum Signal {
         provenance: AgentPubKey,
         message: SessionMessage,
     },
+    DirectMessage {
+        provenance: AgentPubKey,
+        message: DirectMessage,
+    },
     LinkCreated {
         action: SignedActionHashed,
         link_type: LinkTypes,
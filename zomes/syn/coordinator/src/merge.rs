@@ -0,0 +1,165 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::{
+    chunk::commit_state,
+    commit::create_commit,
+    history::{get_commit_entry, CommitGraph},
+    messages::{send_message, MessagePayload, SendMessageInput, SessionMessage},
+    role::ensure_can_edit_workspace,
+    workspace::{
+        get_newest_workspace_tip, get_workspace_entry, get_workspace_session_participants,
+        update_workspace_tip, UpdateWorkspaceTipInput,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum MergeStrategy {
+    /// State already merged by the caller, typically with the app's own merge function
+    Provided { state: SerializedBytes },
+    /// Three-way merge of JSON states against the merge base, the target wins conflicts
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MergeWorkspaceInput {
+    pub source_workspace_hash: EntryHash,
+    pub target_workspace_hash: EntryHash,
+    pub strategy: MergeStrategy,
+    pub meta: Option<SerializedBytes>,
+}
+
+/// Creates a commit on top of both workspace tips and moves the target workspace to it.
+/// Returns the target's tip unchanged if it already contains the source.
+#[hdk_extern]
+pub fn merge_workspace(input: MergeWorkspaceInput) -> ExternResult<Record> {
+    ensure_can_edit_workspace(input.target_workspace_hash.clone())?;
+    let source = get_workspace_entry(input.source_workspace_hash.clone())?;
+    let target = get_workspace_entry(input.target_workspace_hash.clone())?;
+    if source.document_hash != target.document_hash {
+        return Err(wasm_error!(
+            "Can't merge workspaces that belong to different documents"
+        ));
+    }
+
+    let source_tip = get_newest_workspace_tip(input.source_workspace_hash)?
+        .ok_or(wasm_error!("Source workspace has no commits to merge"))?;
+    let target_tip = get_newest_workspace_tip(input.target_workspace_hash.clone())?
+        .ok_or(wasm_error!("Target workspace has no commits to merge into"))?;
+
+    let mut graph = CommitGraph::default();
+    if graph.is_ancestor(&source_tip, &target_tip)? {
+        return get(target_tip, GetOptions::default())?
+            .ok_or(wasm_error!("Could not get the target workspace tip"));
+    }
+
+    let state = match input.strategy {
+        MergeStrategy::Provided { state } => state,
+        MergeStrategy::Json => {
+            let base_state = match graph.merge_base(&source_tip, &target_tip)? {
+                Some(base) => Some(commit_json_state(base)?),
+                None => None,
+            };
+            let merged = merge_json(
+                base_state.as_ref(),
+                Some(&commit_json_state(target_tip.clone())?),
+                Some(&commit_json_state(source_tip.clone())?),
+            )
+            .unwrap_or(Value::Null);
+
+            SerializedBytes::from(UnsafeBytes::from(
+                serde_json::to_vec(&merged)
+                    .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?,
+            ))
+        }
+    };
+
+    let previous_commit_hashes = vec![target_tip, source_tip];
+    let record = create_commit(Commit {
+        state,
+        document_hash: target.document_hash,
+        previous_commit_hashes: previous_commit_hashes.clone(),
+        authors: vec![agent_info()?.agent_initial_pubkey],
+        witnesses: vec![],
+        meta: input.meta,
+        kind: CommitKind::Snapshot,
+        state_chunks: vec![],
+    })?;
+
+    update_workspace_tip(UpdateWorkspaceTipInput {
+        workspace_hash: input.target_workspace_hash.clone(),
+        new_tip_hash: record.action_address().clone(),
+        previous_commit_hashes,
+    })?;
+
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let participants: Vec<AgentPubKey> =
+        get_workspace_session_participants(input.target_workspace_hash.clone())?
+            .into_iter()
+            .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+            .filter(|agent| !agent.eq(&my_pub_key))
+            .collect();
+    send_message(SendMessageInput {
+        message: SessionMessage {
+            workspace_hash: input.target_workspace_hash,
+            sequence: None,
+            payload: MessagePayload::NewCommit {
+                new_commit: record.clone(),
+            },
+        },
+        recipients: participants,
+    })?;
+
+    Ok(record)
+}
+
+fn commit_json_state(commit_hash: ActionHash) -> ExternResult<Value> {
+    let (_, commit) = get_commit_entry(commit_hash.clone())?.ok_or(wasm_error!(
+        WasmErrorInner::Guest(format!("Commit {} not found", commit_hash))
+    ))?;
+
+    serde_json::from_slice(commit_state(&commit)?.bytes()).map_err(|e| {
+        wasm_error!(WasmErrorInner::Guest(format!(
+            "State of commit {} is not JSON: {}",
+            commit_hash, e
+        )))
+    })
+}
+
+/// Three-way merge, `None` meaning absent. Objects merge key by key,
+/// any other conflicting change resolves to `ours`.
+fn merge_json(base: Option<&Value>, ours: Option<&Value>, theirs: Option<&Value>) -> Option<Value> {
+    if ours == theirs || base == theirs {
+        return ours.cloned();
+    }
+    if base == ours {
+        return theirs.cloned();
+    }
+
+    match (ours, theirs) {
+        (Some(Value::Object(ours)), Some(Value::Object(theirs))) => {
+            let base = match base {
+                Some(Value::Object(base)) => Some(base),
+                _ => None,
+            };
+
+            let mut merged = Map::new();
+            for key in ours.keys().chain(theirs.keys()) {
+                if merged.contains_key(key) {
+                    continue;
+                }
+                if let Some(value) = merge_json(
+                    base.and_then(|base| base.get(key)),
+                    ours.get(key),
+                    theirs.get(key),
+                ) {
+                    merged.insert(key.clone(), value);
+                }
+            }
+            Some(Value::Object(merged))
+        }
+        _ => ours.cloned(),
+    }
+}
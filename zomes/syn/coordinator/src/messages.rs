@@ -1,6 +1,13 @@
 use hdk::prelude::*;
 
-#[derive(Serialize, Debug, Deserialize)]
+use crate::{
+    compression::{compress_session_message, fragment_session_message},
+    encryption::encrypt_session_message,
+    recording::record_session_message,
+    workspace::refresh_session_presence,
+};
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
 #[serde(tag = "type")]
 pub enum MessagePayload {
     JoinSession,
@@ -18,12 +25,88 @@ pub enum MessagePayload {
     },
     Heartbeat {
         known_participants: Vec<AgentPubKey>,
+        /// When the sender sent it, for receivers to measure its latency
+        #[serde(default)]
+        sent_at: Option<Timestamp>,
+    },
+    /// The workspace has more than one tip, see `resolve_tips`
+    TipsDiverged {
+        tips: Vec<ActionHash>,
+    },
+    /// The sender is the scribe of the session from `term` on
+    ScribeAnnouncement {
+        term: u64,
+    },
+    /// The sender, scribe until now, passes the role on to `new_scribe`
+    ScribeHandover {
+        new_scribe: AgentPubKey,
+        term: u64,
+    },
+    /// The sender elected `scribe`, who should announce itself for `term`
+    ScribeElection {
+        scribe: AgentPubKey,
+        term: u64,
+    },
+    /// The sender received all of our sequenced messages of the current epoch up to `sequence`
+    Ack {
+        sequence: u64,
+    },
+    /// The sender missed these sequenced messages of our current epoch and asks for them again
+    ResendReq {
+        sequences: Vec<u64>,
+    },
+    /// App specific coordination between session participants, opaque to syn
+    App {
+        kind: String,
+        payload: SerializedBytes,
+    },
+    /// Any payload carrying document state in an encrypted workspace, only ever seen
+    /// on the wire as `send_message` and `recv_remote_signal` unwrap it
+    Encrypted {
+        encrypted_payload: XSalsa20Poly1305EncryptedData,
+    },
+    /// Deflated payload, see `compress_session_message`
+    Compressed {
+        data: SerializedBytes,
+    },
+    /// Piece `index` of `total` of a payload too big for a single signal
+    Fragment {
+        message_id: Vec<u8>,
+        index: u32,
+        total: u32,
+        data: SerializedBytes,
+    },
+    /// The workspace's session key, encrypted from the sender's to our published X25519 key
+    SessionKey {
+        sender_key: X25519PubKey,
+        encrypted_key: XSalsa20Poly1305EncryptedData,
+    },
+    /// We got an encrypted message we have no key for
+    SessionKeyReq,
+    /// The sender holds the same state as the commit, see `attest_commit`
+    CommitAttestation {
+        commit_hash: ActionHash,
+        heads: Vec<String>,
+        signature: Signature,
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Position of a message in the sender's stream of messages that must not get lost
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSequence {
+    /// Picked anew every time the sender joins the session, so receivers notice when its
+    /// counter starts over
+    pub epoch: u64,
+    pub number: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SessionMessage {
     pub workspace_hash: EntryHash,
+    /// Per sender counter for messages that must not get lost, receivers detect
+    /// gaps in it and ask for the missing messages with `ResendReq`
+    #[serde(default)]
+    pub sequence: Option<MessageSequence>,
     pub payload: MessagePayload,
 }
 
@@ -35,7 +118,44 @@ pub struct SendMessageInput {
 
 #[hdk_extern]
 pub fn send_message(input: SendMessageInput) -> ExternResult<()> {
-    send_remote_signal(input.message, input.recipients)?;
+    if let MessagePayload::Heartbeat { .. } = &input.message.payload {
+        refresh_session_presence(input.message.workspace_hash.clone())?;
+    }
+
+    record_session_message(&input.message, &agent_info()?.agent_initial_pubkey)?;
+
+    let message = encrypt_session_message(compress_session_message(input.message)?)?;
+    for fragment in fragment_session_message(message)? {
+        send_remote_signal(RemoteMessage::Session(fragment), input.recipients.clone())?;
+    }
+
+    Ok(())
+}
+
+/// App specific message to a single agent, outside of any workspace session
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectMessage {
+    pub kind: String,
+    pub payload: SerializedBytes,
+}
+
+/// Everything `recv_remote_signal` accepts
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum RemoteMessage {
+    Session(SessionMessage),
+    Direct(DirectMessage),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendDirectMessageInput {
+    pub recipient: AgentPubKey,
+    pub message: DirectMessage,
+}
+
+#[hdk_extern]
+pub fn send_direct_message(input: SendDirectMessageInput) -> ExternResult<()> {
+    send_remote_signal(RemoteMessage::Direct(input.message), vec![input.recipient])?;
 
     Ok(())
 }
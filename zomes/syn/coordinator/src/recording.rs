@@ -0,0 +1,138 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{messages::SessionMessage, utils::create_relaxed};
+
+fn set_session_recording(workspace_hash: EntryHash, active: bool) -> ExternResult<()> {
+    let recorder = SessionRecorder {
+        workspace_hash,
+        active,
+    };
+    create_relaxed(
+        EntryTypes::SessionRecorder(recorder.clone()),
+        recorder.try_into()?,
+    )?;
+
+    Ok(())
+}
+
+/// Starts keeping a private copy of every session message of the workspace we send or receive
+#[hdk_extern]
+pub fn start_session_recording(workspace_hash: EntryHash) -> ExternResult<()> {
+    set_session_recording(workspace_hash, true)
+}
+
+#[hdk_extern]
+pub fn stop_session_recording(workspace_hash: EntryHash) -> ExternResult<()> {
+    set_session_recording(workspace_hash, false)
+}
+
+/// Whether the newest recording toggle for the workspace is on
+#[hdk_extern]
+pub fn is_recording_session(workspace_hash: EntryHash) -> ExternResult<bool> {
+    let records = query(
+        ChainQueryFilter::new()
+            .entry_type(UnitEntryTypes::SessionRecorder.try_into()?)
+            .include_entries(true),
+    )?;
+
+    Ok(records
+        .into_iter()
+        .rev()
+        .filter_map(|record| record.entry().to_app_option::<SessionRecorder>().ok()?)
+        .find(|recorder| recorder.workspace_hash.eq(&workspace_hash))
+        .map(|recorder| recorder.active)
+        .unwrap_or(false))
+}
+
+/// Stores the message if recording is active for its workspace
+pub fn record_session_message(
+    message: &SessionMessage,
+    provenance: &AgentPubKey,
+) -> ExternResult<()> {
+    if !is_recording_session(message.workspace_hash.clone())? {
+        return Ok(());
+    }
+
+    let bytes = ExternIO::encode(message).map_err(|err| wasm_error!(err))?;
+    let recorded = RecordedSessionMessage {
+        workspace_hash: message.workspace_hash.clone(),
+        provenance: provenance.clone(),
+        recorded_at: sys_time()?,
+        message: SerializedBytes::from(UnsafeBytes::from(bytes.0)),
+    };
+    create_relaxed(
+        EntryTypes::RecordedSessionMessage(recorded.clone()),
+        recorded.try_into()?,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplayedSessionMessage {
+    pub provenance: AgentPubKey,
+    pub recorded_at: Timestamp,
+    pub message: SessionMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplaySessionInput {
+    pub workspace_hash: EntryHash,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplayedSession {
+    pub messages: Vec<ReplayedSessionMessage>,
+    pub total: usize,
+    pub has_more: bool,
+}
+
+/// A page of the recorded messages of the workspace, oldest first
+#[hdk_extern]
+pub fn replay_session(input: ReplaySessionInput) -> ExternResult<ReplayedSession> {
+    let records = query(
+        ChainQueryFilter::new()
+            .entry_type(UnitEntryTypes::RecordedSessionMessage.try_into()?)
+            .include_entries(true),
+    )?;
+
+    let mut recorded: Vec<RecordedSessionMessage> = records
+        .into_iter()
+        .filter_map(|record| record.entry().to_app_option::<RecordedSessionMessage>().ok()?)
+        .filter(|recorded| recorded.workspace_hash.eq(&input.workspace_hash))
+        .collect();
+    // Chain order already is recording order, sorting keeps it for equal timestamps
+    recorded.sort_by_key(|recorded| recorded.recorded_at);
+
+    let total = recorded.len();
+    let end = (input.offset + input.limit).min(total);
+
+    // Only the requested page is decoded
+    let mut messages = Vec::new();
+    for recorded in recorded.into_iter().skip(input.offset).take(input.limit) {
+        let message: SessionMessage = ExternIO(recorded.message.bytes().clone())
+            .decode()
+            .map_err(|err| wasm_error!(err))?;
+
+        messages.push(ReplayedSessionMessage {
+            provenance: recorded.provenance,
+            recorded_at: recorded.recorded_at,
+            message,
+        });
+    }
+
+    Ok(ReplayedSession {
+        messages,
+        total,
+        has_more: end < total,
+    })
+}
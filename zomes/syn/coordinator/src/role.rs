@@ -0,0 +1,142 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::utils::{create_link_relaxed, delete_link_relaxed};
+
+/// The first create action of the workspace entry. Others can create the same entry again
+/// later on, only its original creator owns the workspace.
+fn get_original_workspace_create(
+    workspace_hash: EntryHash,
+) -> ExternResult<Option<SignedActionHashed>> {
+    let Some(Details::Entry(details)) = get_details(workspace_hash, GetOptions::default())? else {
+        return Ok(None);
+    };
+
+    Ok(details
+        .actions
+        .into_iter()
+        .filter(|action| matches!(action.action(), Action::Create(_)))
+        .min_by_key(|action| (action.action().timestamp(), action.as_hash().to_string())))
+}
+
+/// Roles assigned by the owner, oldest first
+fn get_role_links(
+    workspace_hash: EntryHash,
+    owner_create: &SignedActionHashed,
+) -> ExternResult<Vec<(Link, RoleTag)>> {
+    let mut links = get_links(
+        GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToRole)?.build(),
+    )?;
+    links.sort_by_key(|link| (link.timestamp, link.create_link_hash.to_string()));
+
+    Ok(links
+        .into_iter()
+        .filter_map(|link| {
+            let tag = RoleTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+                link.tag.clone().into_inner(),
+            )))
+            .ok()?;
+            Some((link, tag))
+        })
+        .filter(|(_, tag)| tag.workspace_create_hash.eq(owner_create.as_hash()))
+        .collect())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetWorkspaceRoleInput {
+    pub workspace_hash: EntryHash,
+    pub agent: AgentPubKey,
+}
+
+/// The original creator of the workspace is its owner, other agents get the role last
+/// assigned to them by the owner. Workspaces without any assigned role stay open: everyone
+/// is an editor. Once roles are assigned, agents without one are viewers.
+#[hdk_extern]
+pub fn get_workspace_role(input: GetWorkspaceRoleInput) -> ExternResult<WorkspaceRole> {
+    let Some(owner_create) = get_original_workspace_create(input.workspace_hash.clone())? else {
+        return Ok(WorkspaceRole::Editor);
+    };
+    if owner_create.action().author().eq(&input.agent) {
+        return Ok(WorkspaceRole::Owner);
+    }
+
+    let role_links = get_role_links(input.workspace_hash, &owner_create)?;
+    if role_links.is_empty() {
+        return Ok(WorkspaceRole::Editor);
+    }
+
+    let agent_target = AnyLinkableHash::from(input.agent);
+    Ok(role_links
+        .into_iter()
+        .rev()
+        .find(|(link, _)| link.target == agent_target)
+        .map(|(_, tag)| tag.role)
+        .unwrap_or(WorkspaceRole::Viewer))
+}
+
+/// Whether the agent may read the workspace: anyone while it's open, otherwise
+/// only its creators and the agents that were assigned a role
+pub fn has_workspace_access(workspace_hash: EntryHash, agent: &AgentPubKey) -> ExternResult<bool> {
+    let Some(owner_create) = get_original_workspace_create(workspace_hash.clone())? else {
+        return Ok(false);
+    };
+    if owner_create.action().author().eq(agent) {
+        return Ok(true);
+    }
+
+    let role_links = get_role_links(workspace_hash, &owner_create)?;
+    let agent_target = AnyLinkableHash::from(agent.clone());
+    Ok(role_links.is_empty() || role_links.iter().any(|(link, _)| link.target == agent_target))
+}
+
+/// Fails if we are a viewer of the workspace
+pub fn ensure_can_edit_workspace(workspace_hash: EntryHash) -> ExternResult<()> {
+    let role = get_workspace_role(GetWorkspaceRoleInput {
+        workspace_hash,
+        agent: agent_info()?.agent_initial_pubkey,
+    })?;
+    if role == WorkspaceRole::Viewer {
+        return Err(wasm_error!("Viewers cannot change the workspace"));
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AssignWorkspaceRoleInput {
+    pub workspace_hash: EntryHash,
+    pub agent: AgentPubKey,
+    pub role: WorkspaceRole,
+}
+
+/// Replaces the role we assigned to the agent, only the owner of the workspace can do this
+#[hdk_extern]
+pub fn assign_workspace_role(input: AssignWorkspaceRoleInput) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let my_create = get_original_workspace_create(input.workspace_hash.clone())?
+        .filter(|create| create.action().author().eq(&my_pub_key))
+        .ok_or(wasm_error!("Only the owner of the workspace can assign roles"))?;
+
+    let agent_target = AnyLinkableHash::from(input.agent.clone());
+    for (link, _) in get_role_links(input.workspace_hash.clone(), &my_create)? {
+        if link.target == agent_target && link.author.eq(&my_pub_key) {
+            delete_link_relaxed(link.create_link_hash)?;
+        }
+    }
+
+    create_link_relaxed(
+        input.workspace_hash,
+        input.agent,
+        LinkTypes::WorkspaceToRole,
+        SerializedBytes::try_from(RoleTag {
+            workspace_create_hash: my_create.as_hash().clone(),
+            role: input.role,
+        })
+        .map_err(|err| wasm_error!(err))?
+        .bytes()
+        .clone(),
+    )?;
+
+    Ok(())
+}
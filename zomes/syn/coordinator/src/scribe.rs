@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+use itertools::Itertools;
+
+use crate::{
+    messages::{send_message, MessagePayload, SendMessageInput, SessionMessage},
+    role::{get_workspace_role, GetWorkspaceRoleInput},
+    utils::create_link_relaxed,
+    workspace::{
+        broadcast_session_message, get_workspace_session_participants, is_workspace_member,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionScribe {
+    pub scribe: AgentPubKey,
+    pub term: u64,
+    pub appointed_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParticipantLatency {
+    pub agent: AgentPubKey,
+    /// Heartbeat delay as measured by the caller
+    pub latency_ms: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ScribeElectionStrategy {
+    LowestPubKey,
+    /// Participants without a measured latency only win if nobody has one
+    BestLatency { latencies: Vec<ParticipantLatency> },
+}
+
+fn session_participants(workspace_hash: EntryHash) -> ExternResult<Vec<AgentPubKey>> {
+    Ok(get_workspace_session_participants(workspace_hash)?
+        .into_iter()
+        .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+        .unique()
+        .collect())
+}
+
+/// Latest appointment and its link, whether or not the scribe is still in the session.
+/// Appointments made by agents that aren't members of the workspace are ignored.
+fn latest_scribe(workspace_hash: EntryHash) -> ExternResult<Option<(SessionScribe, ActionHash)>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(workspace_hash.clone(), LinkTypes::WorkspaceToScribe)?
+            .build(),
+    )?;
+
+    let mut members: BTreeMap<AgentPubKey, bool> = BTreeMap::new();
+    let mut latest: Option<(SessionScribe, ActionHash)> = None;
+    for link in links {
+        let is_member = match members.get(&link.author) {
+            Some(is_member) => *is_member,
+            None => {
+                let is_member = is_workspace_member(workspace_hash.clone(), &link.author)?;
+                members.insert(link.author.clone(), is_member);
+                is_member
+            }
+        };
+        if !is_member {
+            continue;
+        }
+        let Ok(tag) = ScribeTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            link.tag.into_inner(),
+        ))) else {
+            continue;
+        };
+        let Ok(scribe) = AgentPubKey::try_from(link.target) else {
+            continue;
+        };
+        let is_newer = match &latest {
+            Some((current, _)) => {
+                (tag.term, link.timestamp) > (current.term, current.appointed_at)
+            }
+            None => true,
+        };
+        if is_newer {
+            latest = Some((
+                SessionScribe {
+                    scribe,
+                    term: tag.term,
+                    appointed_at: link.timestamp,
+                },
+                link.create_link_hash,
+            ));
+        }
+    }
+
+    Ok(latest)
+}
+
+/// The scribe of the workspace's session, `None` if there is none or they left the session,
+/// in which case the participants should call `elect_scribe`
+#[hdk_extern]
+pub fn get_session_scribe(workspace_hash: EntryHash) -> ExternResult<Option<SessionScribe>> {
+    let participants = session_participants(workspace_hash.clone())?;
+
+    Ok(latest_scribe(workspace_hash)?
+        .map(|(scribe, _)| scribe)
+        .filter(|scribe| participants.contains(&scribe.scribe)))
+}
+
+fn is_viewer(workspace_hash: EntryHash, agent: AgentPubKey) -> ExternResult<bool> {
+    Ok(get_workspace_role(GetWorkspaceRoleInput {
+        workspace_hash,
+        agent,
+    })? == WorkspaceRole::Viewer)
+}
+
+/// Deterministic choice among the candidates, so every participant elects the same scribe
+pub fn pick_scribe(
+    candidates: &[AgentPubKey],
+    strategy: &ScribeElectionStrategy,
+) -> Option<AgentPubKey> {
+    let lowest_pub_key = candidates
+        .iter()
+        .min_by_key(|agent| agent.to_string())
+        .cloned();
+
+    match strategy {
+        ScribeElectionStrategy::LowestPubKey => lowest_pub_key,
+        ScribeElectionStrategy::BestLatency { latencies } => latencies
+            .iter()
+            .filter(|latency| candidates.contains(&latency.agent))
+            .min_by_key(|latency| (latency.latency_ms, latency.agent.to_string()))
+            .map(|latency| latency.agent.clone())
+            .or(lowest_pub_key),
+    }
+}
+
+fn appoint_scribe(
+    workspace_hash: EntryHash,
+    scribe: AgentPubKey,
+    tag: ScribeTag,
+    payload: MessagePayload,
+) -> ExternResult<()> {
+    create_link_relaxed(
+        workspace_hash.clone(),
+        scribe,
+        LinkTypes::WorkspaceToScribe,
+        SerializedBytes::try_from(tag)
+            .map_err(|err| wasm_error!(err))?
+            .bytes()
+            .clone(),
+    )?;
+
+    broadcast_session_message(SessionMessage {
+        workspace_hash,
+        sequence: None,
+        payload,
+    })
+}
+
+/// Tag for the appointment following the latest one
+fn next_term(workspace_hash: EntryHash) -> ExternResult<ScribeTag> {
+    Ok(match latest_scribe(workspace_hash)? {
+        Some((scribe, link_hash)) => ScribeTag {
+            term: scribe.term + 1,
+            previous: Some(link_hash),
+        },
+        None => ScribeTag {
+            term: 0,
+            previous: None,
+        },
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ElectScribeInput {
+    pub workspace_hash: EntryHash,
+    pub strategy: ScribeElectionStrategy,
+}
+
+/// Picks a scribe among the session participants. If it's us we announce ourselves,
+/// otherwise the winner announces itself when it receives the election result.
+#[hdk_extern]
+pub fn elect_scribe(input: ElectScribeInput) -> ExternResult<AgentPubKey> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let participants = session_participants(input.workspace_hash.clone())?;
+    if !participants.contains(&my_pub_key) {
+        return Err(wasm_error!("Only session participants can elect a scribe"));
+    }
+    // Viewers' changes are ignored, they can't commit for everyone
+    let mut candidates = Vec::new();
+    for participant in participants {
+        if !is_viewer(input.workspace_hash.clone(), participant.clone())? {
+            candidates.push(participant);
+        }
+    }
+    let scribe = pick_scribe(&candidates, &input.strategy)
+        .ok_or(wasm_error!("There are no participants who can be the scribe"))?;
+    let tag = next_term(input.workspace_hash.clone())?;
+    let term = tag.term;
+
+    if scribe.eq(&my_pub_key) {
+        appoint_scribe(
+            input.workspace_hash,
+            my_pub_key,
+            tag,
+            MessagePayload::ScribeAnnouncement { term },
+        )?;
+    } else {
+        send_message(SendMessageInput {
+            message: SessionMessage {
+                workspace_hash: input.workspace_hash,
+                sequence: None,
+                payload: MessagePayload::ScribeElection {
+                    scribe: scribe.clone(),
+                    term,
+                },
+            },
+            recipients: candidates
+                .into_iter()
+                .filter(|agent| !agent.eq(&my_pub_key))
+                .collect(),
+        })?;
+    }
+
+    Ok(scribe)
+}
+
+/// Announces ourselves as scribe when a member of the workspace elected us, unless a newer
+/// term was already started or we left the session
+pub fn accept_scribe_election(
+    workspace_hash: EntryHash,
+    elector: &AgentPubKey,
+    term: u64,
+) -> ExternResult<()> {
+    if !is_workspace_member(workspace_hash.clone(), elector)? {
+        return Err(wasm_error!("The elector is not a member of the workspace"));
+    }
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    if !session_participants(workspace_hash.clone())?.contains(&my_pub_key) {
+        return Err(wasm_error!("We are not in the session anymore"));
+    }
+    if is_viewer(workspace_hash.clone(), my_pub_key.clone())? {
+        return Err(wasm_error!("Viewers can't be the scribe"));
+    }
+
+    let tag = next_term(workspace_hash.clone())?;
+    if tag.term > term {
+        return Ok(());
+    }
+    let term = tag.term;
+
+    appoint_scribe(
+        workspace_hash,
+        my_pub_key,
+        tag,
+        MessagePayload::ScribeAnnouncement { term },
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HandOverScribeInput {
+    pub workspace_hash: EntryHash,
+    pub new_scribe: AgentPubKey,
+}
+
+/// Passes the scribe role on to another session participant, only the current scribe can do this
+#[hdk_extern]
+pub fn hand_over_scribe(input: HandOverScribeInput) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let current = get_session_scribe(input.workspace_hash.clone())?;
+    if !matches!(&current, Some(current) if current.scribe.eq(&my_pub_key)) {
+        return Err(wasm_error!("Only the current scribe can hand over the role"));
+    }
+    if !session_participants(input.workspace_hash.clone())?.contains(&input.new_scribe) {
+        return Err(wasm_error!("The new scribe must be a session participant"));
+    }
+    if is_viewer(input.workspace_hash.clone(), input.new_scribe.clone())? {
+        return Err(wasm_error!("Viewers can't be the scribe"));
+    }
+
+    let tag = next_term(input.workspace_hash.clone())?;
+    let term = tag.term;
+    appoint_scribe(
+        input.workspace_hash,
+        input.new_scribe.clone(),
+        tag,
+        MessagePayload::ScribeHandover {
+            new_scribe: input.new_scribe,
+            term,
+        },
+    )
+}
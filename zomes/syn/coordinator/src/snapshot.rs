@@ -0,0 +1,54 @@
+use hc_zome_syn_integrity::*;
+use hdk::prelude::*;
+
+use crate::{commit::get_commit, history::get_commit_entry};
+
+/// Used when the DNA properties don't set a `snapshot_interval`
+pub const DEFAULT_SNAPSHOT_INTERVAL: u32 = 50;
+
+pub fn snapshot_interval() -> ExternResult<u32> {
+    Ok(SynProperties::get()?
+        .snapshot_interval
+        .unwrap_or(DEFAULT_SNAPSHOT_INTERVAL))
+}
+
+/// The snapshot commit the given commit's state builds on: the commit itself if it is a
+/// snapshot, or the base of the delta
+#[hdk_extern]
+pub fn get_latest_snapshot(commit_hash: ActionHash) -> ExternResult<Option<Record>> {
+    let Some((_, commit)) = get_commit_entry(commit_hash.clone())? else {
+        return Ok(None);
+    };
+
+    match commit.kind {
+        CommitKind::Snapshot => get_commit(commit_hash),
+        CommitKind::Delta { base } => get_commit(base),
+    }
+}
+
+/// The kind the next commit on top of `previous_commit_hash` should have: a snapshot once
+/// `snapshot_interval` deltas have piled up since the last one, a delta against it otherwise
+#[hdk_extern]
+pub fn get_next_commit_kind(previous_commit_hash: ActionHash) -> ExternResult<CommitKind> {
+    let interval = snapshot_interval()?;
+
+    let mut deltas: u32 = 0;
+    let mut current = previous_commit_hash;
+    while deltas < interval {
+        let (_, commit) = get_commit_entry(current.clone())?.ok_or(wasm_error!(
+            WasmErrorInner::Guest(format!("Commit {} not found", current))
+        ))?;
+        if commit.kind == CommitKind::Snapshot {
+            return Ok(CommitKind::Delta { base: current });
+        }
+
+        deltas += 1;
+        current = commit
+            .previous_commit_hashes
+            .first()
+            .cloned()
+            .ok_or(wasm_error!("Delta commit has no previous commit"))?;
+    }
+
+    Ok(CommitKind::Snapshot)
+}
@@ -1,19 +1,23 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use hc_zome_syn_integrity::*;
 use hdk::prelude::*;
 use itertools::Itertools;
 
 use crate::{
+    Signal,
+    document::get_authors_for_document,
+    encryption::{ensure_encryption_key, rotate_session_key},
+    history::get_commit_entry,
     messages::{send_message, MessagePayload, SendMessageInput, SessionMessage},
+    role::{ensure_can_edit_workspace, get_workspace_role, GetWorkspaceRoleInput},
+    scribe::{
+        get_session_scribe, hand_over_scribe, pick_scribe, HandOverScribeInput,
+        ScribeElectionStrategy,
+    },
     utils::{create_link_relaxed, create_relaxed, delete_link_relaxed},
 };
 
-#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
-pub struct DocumentToWorkspaceTag {
-    workspace_name: String,
-}
-
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateWorkspaceInput {
     workspace: Workspace,
@@ -23,21 +27,42 @@ pub struct CreateWorkspaceInput {
 #[hdk_extern]
 pub fn create_workspace(input: CreateWorkspaceInput) -> ExternResult<Record> {
     let entry_hash = hash_entry(&input.workspace)?;
+    ensure_workspace_name_available(
+        input.workspace.document_hash.clone(),
+        &input.workspace.name,
+        &entry_hash,
+    )?;
+    // The entry hash is derived from the original name, which a renamed workspace still holds
+    let workspace_target = AnyLinkableHash::from(entry_hash.clone());
+    for link in get_document_workspace_links(input.workspace.document_hash.clone())? {
+        if link.target == workspace_target
+            && decode_workspace_tag(&link)?.workspace_name != input.workspace.name
+        {
+            return Err(wasm_error!(WasmErrorInner::Guest(format!(
+                "The name {} belonged to a workspace that has been renamed",
+                input.workspace.name
+            ))));
+        }
+    }
+
     let action_hash = create_relaxed(
         EntryTypes::Workspace(input.workspace.clone()),
         input.workspace.clone().try_into()?,
     )?;
 
-    create_link_relaxed(
+    let name_claim = claim_workspace_name(
+        input.workspace.document_hash.clone(),
+        entry_hash.clone(),
+        input.workspace.name.clone(),
+    )?;
+    link_document_to_workspace(
         input.workspace.document_hash,
         entry_hash.clone(),
-        LinkTypes::DocumentToWorkspaces,
-        SerializedBytes::try_from(DocumentToWorkspaceTag {
+        DocumentToWorkspaceTag {
             workspace_name: input.workspace.name,
-        })
-        .map_err(|err| wasm_error!(err))?
-        .bytes()
-        .clone(),
+            archived: false,
+            name_claim: Some(name_claim),
+        },
     )?;
 
     if let Some(commit_hash) = input.initial_commit_hash {
@@ -58,47 +83,637 @@ pub fn create_workspace(input: CreateWorkspaceInput) -> ExternResult<Record> {
     ))))
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum ForkSource {
+    /// Branch off the current tip of an existing workspace
+    Workspace { workspace_hash: EntryHash },
+    Commit { commit_hash: ActionHash },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ForkWorkspaceInput {
+    source: ForkSource,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct ForkOriginTag {
+    source_workspace_hash: Option<EntryHash>,
+}
+
+/// Whether any workspace of the document that ever had the commit as a tip is encrypted
+fn is_commit_of_encrypted_workspace(
+    document_hash: AnyDhtHash,
+    commit_hash: &ActionHash,
+) -> ExternResult<bool> {
+    let commit_target = AnyLinkableHash::from(commit_hash.clone());
+    let workspace_hashes: HashSet<EntryHash> = get_document_workspace_links(document_hash)?
+        .into_iter()
+        .filter_map(|link| link.target.into_entry_hash())
+        .collect();
+
+    for workspace_hash in workspace_hashes {
+        let tip_links = get_links(
+            GetLinksInputBuilder::try_new(workspace_hash.clone(), LinkTypes::WorkspaceToTip)?
+                .build(),
+        )?;
+        if tip_links.iter().any(|link| link.target == commit_target)
+            && get_workspace_entry(workspace_hash)?.encrypted
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[hdk_extern]
+pub fn fork_workspace(input: ForkWorkspaceInput) -> ExternResult<Record> {
+    let (commit_hash, source_workspace_hash, encrypted) = match input.source {
+        ForkSource::Workspace { workspace_hash } => {
+            let commit_hash = get_newest_workspace_tip(workspace_hash.clone())?
+                .ok_or(wasm_error!("Source workspace has no commits to fork from"))?;
+            // Forks of private workspaces stay private
+            let encrypted = get_workspace_entry(workspace_hash.clone())?.encrypted;
+            (commit_hash, Some(workspace_hash), encrypted)
+        }
+        ForkSource::Commit { commit_hash } => (commit_hash, None, false),
+    };
+
+    let (_, commit) = get_commit_entry(commit_hash.clone())?
+        .ok_or(wasm_error!("Could not find the commit to fork from"))?;
+    let encrypted = encrypted
+        || is_commit_of_encrypted_workspace(commit.document_hash.clone(), &commit_hash)?;
+
+    let workspace = Workspace {
+        document_hash: commit.document_hash,
+        name: input.name,
+        encrypted,
+    };
+    let workspace_hash = hash_entry(&workspace)?;
+    let record = create_workspace(CreateWorkspaceInput {
+        workspace,
+        initial_commit_hash: Some(commit_hash.clone()),
+    })?;
+
+    let tag = SerializedBytes::try_from(ForkOriginTag {
+        source_workspace_hash,
+    })
+    .map_err(|err| wasm_error!(err))?;
+    create_link_relaxed(
+        workspace_hash,
+        commit_hash,
+        LinkTypes::WorkspaceToForkOrigin,
+        tag.bytes().clone(),
+    )?;
+
+    Ok(record)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceForkOrigin {
+    pub commit_hash: ActionHash,
+    pub source_workspace_hash: Option<EntryHash>,
+}
+
+/// Where a workspace created with `fork_workspace` branched off, `None` for other workspaces
+#[hdk_extern]
+pub fn get_workspace_fork_origin(
+    workspace_hash: EntryHash,
+) -> ExternResult<Option<WorkspaceForkOrigin>> {
+    let links = get_links(
+        GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToForkOrigin)?.build(),
+    )?;
+
+    let Some(link) = links.into_iter().min_by_key(|link| link.timestamp) else {
+        return Ok(None);
+    };
+    let tag = ForkOriginTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        link.tag.into_inner(),
+    )))
+    .map_err(|err| wasm_error!(err))?;
+
+    Ok(Some(WorkspaceForkOrigin {
+        commit_hash: ActionHash::try_from(link.target).map_err(|e| wasm_error!(e))?,
+        source_workspace_hash: tag.source_workspace_hash,
+    }))
+}
+
 #[hdk_extern]
 pub fn get_workspace(workspace_hash: EntryHash) -> ExternResult<Option<Record>> {
     get(workspace_hash, GetOptions::default())
 }
 
+/// Workspaces of the document that haven't been archived
 #[hdk_extern]
 pub fn get_workspaces_for_document(document_hash: AnyDhtHash) -> ExternResult<Vec<Link>> {
+    let links = get_all_workspaces_for_document(document_hash)?;
+
+    let mut workspaces = Vec::new();
+    for link in links {
+        if !decode_workspace_tag(&link)?.archived {
+            workspaces.push(link);
+        }
+    }
+    Ok(workspaces)
+}
+
+/// All workspaces of the document, archived ones included. Of several workspaces that
+/// ended up with the same name, only the one holding the name claim is returned.
+#[hdk_extern]
+pub fn get_all_workspaces_for_document(document_hash: AnyDhtHash) -> ExternResult<Vec<Link>> {
+    let links = get_document_workspace_links(document_hash.clone())?;
+
+    let mut targets_by_name: HashMap<String, HashSet<AnyLinkableHash>> = HashMap::new();
+    for link in links.iter() {
+        targets_by_name
+            .entry(decode_workspace_tag(link)?.workspace_name)
+            .or_default()
+            .insert(link.target.clone());
+    }
+
+    let mut holders: HashMap<String, Option<AnyLinkableHash>> = HashMap::new();
+    for (name, targets) in targets_by_name {
+        if targets.len() > 1 {
+            let holder = get_workspace_name_holder(&document_hash, &name)?;
+            holders.insert(name, holder.map(AnyLinkableHash::from));
+        }
+    }
+
+    let mut workspaces = Vec::new();
+    for link in links {
+        let keep = match holders.get(&decode_workspace_tag(&link)?.workspace_name) {
+            Some(holder) => holder.as_ref() == Some(&link.target),
+            None => true,
+        };
+        if keep {
+            workspaces.push(link);
+        }
+    }
+    Ok(workspaces)
+}
+
+fn get_document_workspace_links(document_hash: AnyDhtHash) -> ExternResult<Vec<Link>> {
     get_links(
         GetLinksInputBuilder::try_new(document_hash, LinkTypes::DocumentToWorkspaces)?.build(),
     )
 }
 
+/// The workspace holding the name in the document, the target of the oldest live claim
+fn get_workspace_name_holder(
+    document_hash: &AnyDhtHash,
+    name: &str,
+) -> ExternResult<Option<EntryHash>> {
+    let claims = get_links(
+        GetLinksInputBuilder::try_new(
+            workspace_name_anchor(document_hash, name)?,
+            LinkTypes::WorkspaceNameClaim,
+        )?
+        .build(),
+    )?;
+
+    Ok(claims
+        .into_iter()
+        .min_by_key(|link| (link.timestamp, link.create_link_hash.to_string()))
+        .and_then(|link| link.target.into_entry_hash()))
+}
+
+fn claim_workspace_name(
+    document_hash: AnyDhtHash,
+    workspace_hash: EntryHash,
+    name: String,
+) -> ExternResult<ActionHash> {
+    create_link_relaxed(
+        workspace_name_anchor(&document_hash, &name)?,
+        workspace_hash,
+        LinkTypes::WorkspaceNameClaim,
+        SerializedBytes::try_from(WorkspaceNameClaimTag {
+            document_hash,
+            workspace_name: name,
+        })
+        .map_err(|err| wasm_error!(err))?
+        .bytes()
+        .clone(),
+    )
+}
+
+fn link_document_to_workspace(
+    document_hash: AnyDhtHash,
+    workspace_hash: EntryHash,
+    tag: DocumentToWorkspaceTag,
+) -> ExternResult<ActionHash> {
+    create_link_relaxed(
+        document_hash,
+        workspace_hash,
+        LinkTypes::DocumentToWorkspaces,
+        SerializedBytes::try_from(tag)
+            .map_err(|err| wasm_error!(err))?
+            .bytes()
+            .clone(),
+    )
+}
+
+fn decode_workspace_tag(link: &Link) -> ExternResult<DocumentToWorkspaceTag> {
+    DocumentToWorkspaceTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        link.tag.clone().into_inner(),
+    )))
+    .map_err(|err| wasm_error!(err))
+}
+
+/// The workspace's document and the `DocumentToWorkspaces` links from it to this workspace
+fn get_document_links_for_workspace(
+    workspace_hash: EntryHash,
+) -> ExternResult<(AnyDhtHash, Vec<Link>)> {
+    let workspace = get_workspace_entry(workspace_hash.clone())?;
+    let target = AnyLinkableHash::from(workspace_hash);
+
+    let links = get_document_workspace_links(workspace.document_hash.clone())?
+        .into_iter()
+        .filter(|link| link.target == target)
+        .collect();
+    Ok((workspace.document_hash, links))
+}
+
+/// Our own `DocumentToWorkspaces` links to the workspace, the only ones we may rewrite
+fn get_own_document_links_for_workspace(
+    workspace_hash: EntryHash,
+) -> ExternResult<(AnyDhtHash, Vec<Link>)> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let (document_hash, links) = get_document_links_for_workspace(workspace_hash)?;
+
+    Ok((
+        document_hash,
+        links
+            .into_iter()
+            .filter(|link| link.author.eq(&my_pub_key))
+            .collect(),
+    ))
+}
+
+/// Fails unless we linked the workspace to its document, as only those links may be rewritten
+fn ensure_own_document_links(workspace_hash: EntryHash, action: &str) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let (_, links) = get_document_links_for_workspace(workspace_hash)?;
+
+    if links.is_empty() {
+        return Err(wasm_error!("Workspace has been deleted"));
+    }
+    if !links.iter().any(|link| link.author.eq(&my_pub_key)) {
+        return Err(wasm_error!(WasmErrorInner::Guest(format!(
+            "Only the agents that created the workspace can {} it",
+            action
+        ))));
+    }
+    Ok(())
+}
+
+fn ensure_no_other_participants(workspace_hash: EntryHash) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let others = get_workspace_session_participants(workspace_hash)?
+        .into_iter()
+        .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+        .any(|agent| !agent.eq(&my_pub_key));
+
+    if others {
+        return Err(wasm_error!(
+            "Workspace still has participants in its session"
+        ));
+    }
+    Ok(())
+}
+
+fn set_workspace_archived(workspace_hash: EntryHash, archived: bool) -> ExternResult<()> {
+    ensure_can_edit_workspace(workspace_hash.clone())?;
+    ensure_own_document_links(workspace_hash.clone(), "archive")?;
+    ensure_no_other_participants(workspace_hash.clone())?;
+
+    let (document_hash, links) = get_own_document_links_for_workspace(workspace_hash.clone())?;
+    for link in links {
+        let tag = decode_workspace_tag(&link)?;
+        if tag.archived == archived {
+            continue;
+        }
+
+        // The name stays claimed while archived, links from before claims existed get one
+        let name_claim = match tag.name_claim {
+            Some(name_claim) => name_claim,
+            None => claim_workspace_name(
+                document_hash.clone(),
+                workspace_hash.clone(),
+                tag.workspace_name.clone(),
+            )?,
+        };
+        delete_link_relaxed(link.create_link_hash)?;
+        link_document_to_workspace(
+            document_hash.clone(),
+            workspace_hash.clone(),
+            DocumentToWorkspaceTag {
+                workspace_name: tag.workspace_name,
+                archived,
+                name_claim: Some(name_claim),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Fails if `name` is taken in the document by a workspace other than `workspace_hash`
+fn ensure_workspace_name_available(
+    document_hash: AnyDhtHash,
+    name: &str,
+    workspace_hash: &EntryHash,
+) -> ExternResult<()> {
+    let workspace_target = AnyLinkableHash::from(workspace_hash.clone());
+    let taken = |name: &str| {
+        wasm_error!(WasmErrorInner::Guest(format!(
+            "There already is a workspace named {} for this document",
+            name
+        )))
+    };
+
+    let holder = get_workspace_name_holder(&document_hash, name)?;
+    if matches!(holder, Some(holder) if !holder.eq(workspace_hash)) {
+        return Err(taken(name));
+    }
+    for link in get_document_workspace_links(document_hash)? {
+        if link.target != workspace_target && decode_workspace_tag(&link)?.workspace_name == name {
+            return Err(taken(name));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Debug)]
-pub struct UpdateWorkspaceTipInput {
+pub struct GetWorkspaceByNameInput {
+    document_hash: AnyDhtHash,
+    name: String,
+}
+
+/// Looks up by the current name, which may differ from the name in the workspace entry after a rename
+#[hdk_extern]
+pub fn get_workspace_by_name(input: GetWorkspaceByNameInput) -> ExternResult<Option<EntryHash>> {
+    if let Some(holder) = get_workspace_name_holder(&input.document_hash, &input.name)? {
+        return Ok(Some(holder));
+    }
+    // Workspaces linked before names were claimed
+    for link in get_document_workspace_links(input.document_hash)? {
+        if decode_workspace_tag(&link)?.workspace_name == input.name {
+            return Ok(Some(
+                EntryHash::try_from(link.target).map_err(|e| wasm_error!(e))?,
+            ));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RenameWorkspaceInput {
     workspace_hash: EntryHash,
-    new_tip_hash: ActionHash,
-    previous_commit_hashes: Vec<ActionHash>,
+    name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
-pub struct PreviousCommitsTag(pub Vec<ActionHash>);
+/// Only the link tag changes, so the workspace keeps its hash, tips and participants
+#[hdk_extern]
+pub fn rename_workspace(input: RenameWorkspaceInput) -> ExternResult<()> {
+    ensure_can_edit_workspace(input.workspace_hash.clone())?;
+    ensure_own_document_links(input.workspace_hash.clone(), "rename")?;
+    let (document_hash, links) =
+        get_own_document_links_for_workspace(input.workspace_hash.clone())?;
+    ensure_workspace_name_available(document_hash.clone(), &input.name, &input.workspace_hash)?;
+
+    let mut name_claim = None;
+    for link in links {
+        let tag = decode_workspace_tag(&link)?;
+        if tag.workspace_name == input.name {
+            continue;
+        }
+
+        let new_claim = match name_claim.clone() {
+            Some(claim) => claim,
+            None => claim_workspace_name(
+                document_hash.clone(),
+                input.workspace_hash.clone(),
+                input.name.clone(),
+            )?,
+        };
+        name_claim = Some(new_claim.clone());
+
+        delete_link_relaxed(link.create_link_hash)?;
+        if let Some(old_claim) = tag.name_claim {
+            delete_link_relaxed(old_claim)?;
+        }
+        link_document_to_workspace(
+            document_hash.clone(),
+            input.workspace_hash.clone(),
+            DocumentToWorkspaceTag {
+                workspace_name: input.name.clone(),
+                archived: tag.archived,
+                name_claim: Some(new_claim),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Hides the workspace from `get_workspaces_for_document`, keeping its history reachable
+#[hdk_extern]
+pub fn archive_workspace(workspace_hash: EntryHash) -> ExternResult<()> {
+    set_workspace_archived(workspace_hash, true)
+}
+
+#[hdk_extern]
+pub fn unarchive_workspace(workspace_hash: EntryHash) -> ExternResult<()> {
+    set_workspace_archived(workspace_hash, false)
+}
+
+/// Unlinks the workspace from its document. Its commits are kept, as other workspaces may share them.
+#[hdk_extern]
+pub fn delete_workspace(workspace_hash: EntryHash) -> ExternResult<()> {
+    ensure_can_edit_workspace(workspace_hash.clone())?;
+    ensure_own_document_links(workspace_hash.clone(), "delete")?;
+    ensure_no_other_participants(workspace_hash.clone())?;
+
+    let (_, links) = get_own_document_links_for_workspace(workspace_hash)?;
+    for link in links {
+        let tag = decode_workspace_tag(&link)?;
+        delete_link_relaxed(link.create_link_hash)?;
+        if let Some(name_claim) = tag.name_claim {
+            delete_link_relaxed(name_claim)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateWorkspaceTipInput {
+    pub workspace_hash: EntryHash,
+    pub new_tip_hash: ActionHash,
+    /// Tips the new tip replaces, each one a previous commit of the new tip
+    pub previous_commit_hashes: Vec<ActionHash>,
+}
 
 #[hdk_extern]
 pub fn update_workspace_tip(input: UpdateWorkspaceTipInput) -> ExternResult<()> {
+    ensure_can_edit_workspace(input.workspace_hash.clone())?;
+
     let tag = SerializedBytes::try_from(PreviousCommitsTag(input.previous_commit_hashes.clone()))
         .map_err(|err| wasm_error!(err))?;
 
     create_link_relaxed(
-        input.workspace_hash,
+        input.workspace_hash.clone(),
         input.new_tip_hash,
         LinkTypes::WorkspaceToTip,
         tag.bytes().clone(),
     )?;
 
+    let tips = get_workspace_tips(input.workspace_hash.clone())?;
+    if tips.len() > 1 {
+        signal_tips_diverged(input.workspace_hash, tips)?;
+    }
+
     Ok(())
 }
 
+fn signal_tips_diverged(workspace_hash: EntryHash, tips: Vec<Link>) -> ExternResult<()> {
+    broadcast_session_message(SessionMessage {
+        workspace_hash,
+        sequence: None,
+        payload: MessagePayload::TipsDiverged {
+            tips: tips
+                .into_iter()
+                .filter_map(|l| ActionHash::try_from(l.target).ok())
+                .collect(),
+        },
+    })
+}
+
+/// Sends the message to the other session participants and signals it to our own UI as well
+pub fn broadcast_session_message(message: SessionMessage) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+
+    let participants: Vec<AgentPubKey> =
+        get_workspace_session_participants(message.workspace_hash.clone())?
+            .into_iter()
+            .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+            .filter(|agent| !agent.eq(&my_pub_key))
+            .unique()
+            .collect();
+    send_message(SendMessageInput {
+        message: message.clone(),
+        recipients: participants,
+    })?;
+
+    emit_signal(Signal::SessionMessage {
+        provenance: my_pub_key,
+        message,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResolveTipsInput {
+    workspace_hash: EntryHash,
+    /// The tip that supersedes all the others, picked deterministically if not given
+    winner: Option<ActionHash>,
+}
+
+/// Records which commit supersedes the other tips of a diverged workspace,
+/// so that `get_workspace_tips` returns the same single head to everyone
+#[hdk_extern]
+pub fn resolve_tips(input: ResolveTipsInput) -> ExternResult<ActionHash> {
+    ensure_can_edit_workspace(input.workspace_hash.clone())?;
+    let tips = get_workspace_tips(input.workspace_hash.clone())?;
+
+    let mut tip_hashes: Vec<(Timestamp, ActionHash)> = Vec::new();
+    let mut tip_links: Vec<ActionHash> = Vec::new();
+    for tip in tips {
+        tip_hashes.push((
+            tip.timestamp,
+            ActionHash::try_from(tip.target).map_err(|e| wasm_error!(e))?,
+        ));
+        tip_links.push(tip.create_link_hash);
+    }
+
+    let winner = match input.winner {
+        Some(winner) => {
+            if !tip_hashes.iter().any(|(_, hash)| hash.eq(&winner)) {
+                return Err(wasm_error!("The winner must be one of the workspace tips"));
+            }
+            winner
+        }
+        None => {
+            tip_hashes
+                .iter()
+                .max_by_key(|(timestamp, hash)| (*timestamp, hash.to_string()))
+                .ok_or(wasm_error!("Workspace has no tips"))?
+                .1
+                .clone()
+        }
+    };
+
+    let superseded: Vec<ActionHash> = tip_hashes
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .filter(|hash| !hash.eq(&winner))
+        .collect();
+    if superseded.is_empty() {
+        return Ok(winner);
+    }
+
+    let tag = SerializedBytes::try_from(TipResolutionTag {
+        losers: superseded,
+        tip_links,
+    })
+    .map_err(|err| wasm_error!(err))?;
+    create_link_relaxed(
+        input.workspace_hash,
+        winner.clone(),
+        LinkTypes::WorkspaceToTipResolution,
+        tag.bytes().clone(),
+    )?;
+
+    Ok(winner)
+}
+
+/// Tips hidden by `resolve_tips`. Resolutions apply oldest first and a resolution whose
+/// winner was already superseded is ignored, so concurrent resolutions still converge.
+fn get_superseded_tips(workspace_hash: EntryHash) -> ExternResult<HashSet<ActionHash>> {
+    let mut links = get_links(
+        GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToTipResolution)?
+            .build(),
+    )?;
+    links.sort_by_key(|l| (l.timestamp, l.create_link_hash.to_string()));
+
+    let mut superseded = HashSet::new();
+    for l in links {
+        let winner = ActionHash::try_from(l.target.clone()).map_err(|e| wasm_error!(e))?;
+        if superseded.contains(&winner) {
+            continue;
+        }
+
+        let Ok(resolution) = TipResolutionTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+            l.tag.clone().into_inner(),
+        ))) else {
+            continue;
+        };
+        superseded.extend(
+            resolution
+                .losers
+                .into_iter()
+                .filter(|loser| !loser.eq(&winner)),
+        );
+    }
+
+    Ok(superseded)
+}
+
 #[hdk_extern]
 pub fn get_workspace_tips(workspace_hash: EntryHash) -> ExternResult<Vec<Link>> {
     let links = get_links(
-        GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToTip)?.build(),
+        GetLinksInputBuilder::try_new(workspace_hash.clone(), LinkTypes::WorkspaceToTip)?.build(),
     )?;
 
     let mut tips: HashMap<ActionHash, Link> = HashMap::new();
@@ -121,18 +736,152 @@ pub fn get_workspace_tips(workspace_hash: EntryHash) -> ExternResult<Vec<Link>>
     for p in tips_previous {
         tips.remove(&p);
     }
+    if tips.len() > 1 {
+        for p in get_superseded_tips(workspace_hash)? {
+            tips.remove(&p);
+        }
+    }
     Ok(tips.into_values().collect())
 }
 
-#[hdk_extern]
-pub fn get_workspace_session_participants(workspace_hash: EntryHash) -> ExternResult<Vec<Link>> {
+/// With diverged tips, the one most recently pointed to
+pub fn get_newest_workspace_tip(workspace_hash: EntryHash) -> ExternResult<Option<ActionHash>> {
+    let Some(tip) = get_workspace_tips(workspace_hash)?
+        .into_iter()
+        .max_by_key(|link| link.timestamp)
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        ActionHash::try_from(tip.target).map_err(|e| wasm_error!(e))?,
+    ))
+}
+
+pub fn get_workspace_entry(workspace_hash: EntryHash) -> ExternResult<Workspace> {
+    let record = get_workspace(workspace_hash)?.ok_or(wasm_error!("Workspace not found"))?;
+
+    record
+        .entry()
+        .to_app_option::<Workspace>()
+        .map_err(|e| wasm_error!(WasmErrorInner::Guest(e.to_string())))?
+        .ok_or(wasm_error!("Expected a Workspace entry"))
+}
+
+fn participant_ttl_micros() -> ExternResult<i64> {
+    Ok(SynProperties::get()?.participant_ttl_micros())
+}
+
+fn participant_link_expires_at(link: &Link, ttl: i64) -> Timestamp {
+    ParticipantTag::expires_at(link.timestamp, link.tag.clone(), ttl)
+}
+
+fn get_all_participant_links(workspace_hash: EntryHash) -> ExternResult<Vec<Link>> {
     get_links(
         GetLinksInputBuilder::try_new(workspace_hash, LinkTypes::WorkspaceToParticipant)?.build(),
     )
 }
 
+/// Whether the agent may send us messages about this workspace: a session participant,
+/// even one whose heartbeats are late, or an author of the workspace's document
+pub fn is_workspace_member(workspace_hash: EntryHash, agent: &AgentPubKey) -> ExternResult<bool> {
+    let is_participant = get_all_participant_links(workspace_hash.clone())?
+        .into_iter()
+        .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+        .any(|participant| participant.eq(agent));
+    if is_participant {
+        return Ok(true);
+    }
+
+    let Some(record) = get_workspace(workspace_hash)? else {
+        return Ok(false);
+    };
+    let Ok(Some(workspace)) = record.entry().to_app_option::<Workspace>() else {
+        return Ok(false);
+    };
+
+    Ok(get_authors_for_document(workspace.document_hash)?
+        .into_iter()
+        .filter_map(|l| AgentPubKey::try_from(l.target).ok())
+        .any(|author| author.eq(agent)))
+}
+
+/// Participants whose participant link hasn't expired yet, see `refresh_session_presence`
+#[hdk_extern]
+pub fn get_workspace_session_participants(workspace_hash: EntryHash) -> ExternResult<Vec<Link>> {
+    let ttl = participant_ttl_micros()?;
+    let now = sys_time()?;
+
+    Ok(get_all_participant_links(workspace_hash)?
+        .into_iter()
+        .filter(|link| participant_link_expires_at(link, ttl) > now)
+        .collect())
+}
+
+/// Adds a participant link expiring a TTL from now, unless ours still has more than
+/// three quarters of the TTL left. Expired links stay until `remove_stale_session_participants`.
+pub fn refresh_session_presence(workspace_hash: EntryHash) -> ExternResult<()> {
+    let my_pub_key = agent_info()?.agent_initial_pubkey;
+    let ttl = participant_ttl_micros()?;
+    let now = sys_time()?.as_micros();
+
+    let expires_at = get_all_participant_links(workspace_hash.clone())?
+        .into_iter()
+        .filter(|l| {
+            AgentPubKey::try_from(l.target.clone())
+                .map(|agent| agent == my_pub_key)
+                .unwrap_or(false)
+        })
+        .map(|l| participant_link_expires_at(&l, ttl).as_micros())
+        .max();
+    if matches!(expires_at, Some(expires_at) if expires_at - now > ttl * 3 / 4) {
+        return Ok(());
+    }
+
+    create_link_relaxed(
+        workspace_hash,
+        my_pub_key,
+        LinkTypes::WorkspaceToParticipant,
+        SerializedBytes::try_from(ParticipantTag {
+            expires_at: Timestamp::from_micros(now + ttl),
+        })
+        .map_err(|err| wasm_error!(err))?
+        .bytes()
+        .clone(),
+    )?;
+
+    Ok(())
+}
+
+/// Deletes the expired participant links, of crashed clients or left behind by our own
+/// presence refreshes. Returns how many links were deleted.
+#[hdk_extern]
+pub fn remove_stale_session_participants(workspace_hash: EntryHash) -> ExternResult<usize> {
+    let ttl = participant_ttl_micros()?;
+    let now = sys_time()?;
+
+    let mut removed = 0;
+    for link in get_all_participant_links(workspace_hash)? {
+        if participant_link_expires_at(&link, ttl) < now {
+            delete_link_relaxed(link.create_link_hash)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct JoinWorkspaceSessionOutput {
+    pub participants: Vec<AgentPubKey>,
+    /// Our role in the workspace, the others ignore our changes if we are a viewer
+    pub role: WorkspaceRole,
+}
+
 #[hdk_extern]
-pub fn join_workspace_session(workspace_hash: EntryHash) -> ExternResult<Vec<AgentPubKey>> {
+pub fn join_workspace_session(
+    workspace_hash: EntryHash,
+) -> ExternResult<JoinWorkspaceSessionOutput> {
     let my_pub_key = agent_info()?.agent_initial_pubkey;
     let participants_links = get_workspace_session_participants(workspace_hash.clone())?;
 
@@ -143,36 +892,41 @@ pub fn join_workspace_session(workspace_hash: EntryHash) -> ExternResult<Vec<Age
         .collect();
 
     if !participants.contains(&my_pub_key) {
-        create_link_relaxed(
-            workspace_hash.clone(),
-            my_pub_key,
-            LinkTypes::WorkspaceToParticipant,
-            (),
-        )?;
+        refresh_session_presence(workspace_hash.clone())?;
+
+        if get_workspace_entry(workspace_hash.clone())?.encrypted {
+            // Others share the session key with us once they see our encryption key
+            ensure_encryption_key(workspace_hash.clone())?;
+            if participants.is_empty() {
+                rotate_session_key(workspace_hash.clone())?;
+            }
+        }
         // Signal
         send_message(SendMessageInput {
             message: SessionMessage {
                 workspace_hash: workspace_hash.clone(),
+                sequence: None,
                 payload: MessagePayload::JoinSession,
             },
             recipients: participants.clone(),
         })?;
     }
 
-    Ok(participants)
+    let role = get_workspace_role(GetWorkspaceRoleInput {
+        workspace_hash,
+        agent: my_pub_key,
+    })?;
+
+    Ok(JoinWorkspaceSessionOutput { participants, role })
 }
 
 #[hdk_extern]
 pub fn leave_workspace_session(workspace_hash: EntryHash) -> ExternResult<()> {
     let my_pub_key = agent_info()?.agent_initial_pubkey;
 
-    let links = get_links(
-        GetLinksInputBuilder::try_new(workspace_hash.clone(), LinkTypes::WorkspaceToParticipant)?
-            .build(),
-    )?;
+    let links = get_all_participant_links(workspace_hash.clone())?;
 
     let my_links: Vec<Link> = links
-        .clone()
         .into_iter()
         .filter(|l| {
             AgentPubKey::try_from(l.target.clone())
@@ -182,12 +936,27 @@ pub fn leave_workspace_session(workspace_hash: EntryHash) -> ExternResult<()> {
         //        .filter(|l| AgentPubKey::from(EntryHash::from(l.target.clone())).eq(&my_pub_key))
         .collect();
 
-    let participants: Vec<AgentPubKey> = links
+    let participants: Vec<AgentPubKey> = get_workspace_session_participants(workspace_hash.clone())?
         .into_iter()
         .filter_map(|l: Link| AgentPubKey::try_from(l.target).ok())
         .filter(|agent_pub_key| !agent_pub_key.eq(&my_pub_key))
+        .unique()
         .collect();
 
+    // Don't leave the session without a scribe
+    let is_scribe = matches!(
+        get_session_scribe(workspace_hash.clone())?,
+        Some(scribe) if scribe.scribe.eq(&my_pub_key)
+    );
+    if is_scribe {
+        if let Some(new_scribe) = pick_scribe(&participants, &ScribeElectionStrategy::LowestPubKey) {
+            hand_over_scribe(HandOverScribeInput {
+                workspace_hash: workspace_hash.clone(),
+                new_scribe,
+            })?;
+        }
+    }
+
     for my_link in my_links {
         delete_link_relaxed(my_link.create_link_hash)?;
     }
@@ -196,6 +965,7 @@ pub fn leave_workspace_session(workspace_hash: EntryHash) -> ExternResult<()> {
     send_message(SendMessageInput {
         message: SessionMessage {
             workspace_hash: workspace_hash.clone(),
+            sequence: None,
             payload: MessagePayload::LeaveSession,
         },
         recipients: participants.clone(),
@@ -0,0 +1,76 @@
+use hdi::prelude::*;
+
+use crate::must_get_commit;
+
+/// What a witness signs: the commit and the heads of the state it holds when the commit
+/// arrives. Serialized states differ between agents holding the same changes, the heads don't.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AttestedState {
+    pub commit_hash: ActionHash,
+    pub heads: Vec<String>,
+}
+
+/// Tag of the `CommitToAttestations` links, which point from the commit to the witness
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct AttestationTag {
+    pub witness: AgentPubKey,
+    /// Heads of the witness' state, to be compared with those of the commit's state
+    pub heads: Vec<String>,
+    pub signature: Signature,
+}
+
+pub fn verify_attestation(
+    commit_hash: &ActionHash,
+    attestation: &AttestationTag,
+) -> ExternResult<bool> {
+    verify_signature(
+        attestation.witness.clone(),
+        attestation.signature.clone(),
+        AttestedState {
+            commit_hash: commit_hash.clone(),
+            heads: attestation.heads.clone(),
+        },
+    )
+}
+
+pub fn validate_create_link_commit_to_attestations(
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let attestation = match AttestationTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(attestation) => attestation,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed CommitToAttestations tag: {e:?}"
+            )))
+        }
+    };
+
+    if AgentPubKey::try_from(target_address).ok().as_ref() != Some(&attestation.witness) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "CommitToAttestations links must point to the witness".into(),
+        ));
+    }
+
+    let Some(commit_hash) = base_address.into_action_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "CommitToAttestations links must start at a commit".into(),
+        ));
+    };
+    if must_get_commit(commit_hash.clone())?.is_none() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "CommitToAttestations links must start at a commit".into(),
+        ));
+    }
+
+    if !verify_attestation(&commit_hash, &attestation)? {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The witness signature doesn't match the commit".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -11,6 +11,57 @@ pub struct Cart {
     pub created_at: Timestamp,
     pub cart_name: String,
     pub meta: Option<SerializedBytes>, 
+    #[serde(default)]
+    pub items: Vec<CartItem>,
+    #[serde(default)]
+    pub status_history: Vec<CartStatusChange>,
+    /// `DeliverySlot` reserved for this cart
+    #[serde(default)]
+    pub delivery_slot: Option<ActionHash>,
+}
+
+/// A single line of a cart, pointing at a `Product` in the catalog
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartItem {
+    pub product_hash: ActionHash,
+    pub quantity: u32,
+    /// Who pays for this line in a shared cart, empty means the cart owner pays for all of it
+    #[serde(default)]
+    pub assignments: Vec<ItemShare>,
+    #[serde(default)]
+    pub substitution: SubstitutionPolicy,
+}
+
+/// What the shopper accepts in place of a product that is out of stock at checkout
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type")]
+pub enum SubstitutionPolicy {
+    #[default]
+    None,
+    AnySameType,
+    Specific {
+        product_hash: ActionHash,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SubstitutionProposal {
+    pub product_hash: ActionHash,
+    /// Available alternates in catalog order; empty if the policy allows none
+    pub substitutes: Vec<ActionHash>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ItemShare {
+    pub agent: AgentPubKey,
+    /// Relative weight of this agent's share among the line's assignments
+    pub ratio: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AgentShare {
+    pub agent: AgentPubKey,
+    pub amount: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -20,6 +71,42 @@ pub enum CartStatus {
     Processed,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartStatusChange {
+    pub status: CartStatus,
+    pub changed_at: Timestamp,
+}
+
+/// Immutable record of a cart at checkout time
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct Order {
+    pub cart_hash: ActionHash,
+    pub document_hash: AnyDhtHash,
+    pub owner: AgentPubKey,
+    pub checked_out_at: Timestamp,
+    pub items: Vec<CartItem>,
+    pub total: f64,
+    pub bill_split: Vec<AgentShare>,
+    #[serde(default)]
+    pub substitutions: Vec<SubstitutionProposal>,
+    #[serde(default)]
+    pub delivery_slot: Option<ActionHash>,
+    /// Catalog data of every item as it was at checkout
+    #[serde(default)]
+    pub lines: Vec<OrderLine>,
+}
+
+/// A cart item priced and categorized at checkout, so later catalog changes don't rewrite the order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderLine {
+    pub product_hash: ActionHash,
+    pub quantity: u32,
+    pub unit_price: f32,
+    pub category: String,
+    pub subcategory: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CartCloneInfo {
     pub dna_hash: DnaHash,
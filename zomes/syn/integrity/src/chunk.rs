@@ -0,0 +1,50 @@
+use hdi::prelude::*;
+
+/// States bigger than this are split into `StateChunk` entries, well below the entry size limit
+pub const STATE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Piece of a document or commit state too big for a single entry. Chunks are
+/// referenced by entry hash, so identical pieces are only stored once.
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct StateChunk {
+    pub data: SerializedBytes,
+}
+
+/// Checks that a chunked state left `state` empty and that every chunk
+/// exists with the hash it is referenced by
+pub fn validate_state_chunks(
+    state: &SerializedBytes,
+    state_chunks: &[EntryHash],
+) -> ExternResult<ValidateCallbackResult> {
+    if state_chunks.is_empty() {
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    if !state.bytes().is_empty() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "A chunked state must leave the inline state empty".into(),
+        ));
+    }
+
+    for chunk_hash in state_chunks {
+        // Only returns entries whose content hashes to `chunk_hash`
+        let entry = must_get_entry(chunk_hash.clone())?;
+        let chunk = match StateChunk::try_from(entry.content) {
+            Ok(chunk) => chunk,
+            Err(_) => {
+                return Ok(ValidateCallbackResult::Invalid(format!(
+                    "Entry {} is not a state chunk",
+                    chunk_hash
+                )))
+            }
+        };
+        if chunk.data.bytes().len() > STATE_CHUNK_SIZE {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "State chunk {} is bigger than {} bytes",
+                chunk_hash, STATE_CHUNK_SIZE
+            )));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -1,5 +1,7 @@
 use hdi::prelude::*;
 
+use crate::validate_state_chunks;
+
 #[hdk_entry_helper]
 #[derive(Clone)]
 pub struct Commit {
@@ -9,7 +11,78 @@ pub struct Commit {
     pub previous_commit_hashes: Vec<ActionHash>,
 
     pub authors: Vec<AgentPubKey>,
-    pub witnesses: Vec<AgentPubKey>, // maybe?
+    /// Left empty by syn, witness attestations are linked to the commit once it exists,
+    /// see `AttestationTag`
+    pub witnesses: Vec<AgentPubKey>,
 
     pub meta: Option<SerializedBytes>,
+
+    #[serde(default)]
+    pub kind: CommitKind,
+
+    /// Set when `state` was too big for a single entry and got split into `StateChunk`s
+    #[serde(default)]
+    pub state_chunks: Vec<EntryHash>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(tag = "type")]
+pub enum CommitKind {
+    /// `state` is the full state of the document
+    #[default]
+    Snapshot,
+    /// `state` only holds the changes since the `base` snapshot commit
+    Delta { base: ActionHash },
+}
+
+pub fn validate_create_commit(commit: Commit) -> ExternResult<ValidateCallbackResult> {
+    let chunks_validation = validate_state_chunks(&commit.state, &commit.state_chunks)?;
+    if chunks_validation != ValidateCallbackResult::Valid {
+        return Ok(chunks_validation);
+    }
+
+    if let CommitKind::Delta { base } = &commit.kind {
+        let record = must_get_valid_record(base.clone())?;
+        let base_commit = match record.entry().to_app_option::<Commit>() {
+            Ok(Some(base_commit)) => base_commit,
+            _ => {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "The base of a delta commit must be a commit".into(),
+                ))
+            }
+        };
+        if base_commit.kind != CommitKind::Snapshot {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The base of a delta commit must be a snapshot".into(),
+            ));
+        }
+        if base_commit.document_hash != commit.document_hash {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The base of a delta commit must belong to the same document".into(),
+            ));
+        }
+
+        // The previous commit is either the base or a delta already checked against it
+        let mut builds_on_base = false;
+        for previous_commit_hash in &commit.previous_commit_hashes {
+            if previous_commit_hash.eq(base) {
+                builds_on_base = true;
+                break;
+            }
+            let record = must_get_valid_record(previous_commit_hash.clone())?;
+            if let Ok(Some(previous_commit)) = record.entry().to_app_option::<Commit>() {
+                if previous_commit.kind == commit.kind {
+                    builds_on_base = true;
+                    break;
+                }
+            }
+        }
+        if !builds_on_base {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The base of a delta commit must be one of its ancestors".into(),
+            ));
+        }
+    }
+
+    Ok(ValidateCallbackResult::Valid)
 }
@@ -0,0 +1,115 @@
+use hdi::prelude::*;
+
+use crate::SynProperties;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FulfillmentKind {
+    Delivery,
+    Pickup,
+}
+
+/// A time window in which the store can deliver or hand over up to `capacity` orders
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct DeliverySlot {
+    pub kind: FulfillmentKind,
+    pub location: String,
+    pub starts_at: Timestamp,
+    pub ends_at: Timestamp,
+    pub capacity: u32,
+}
+
+pub fn validate_create_delivery_slot(
+    action: &Create,
+    slot: DeliverySlot,
+) -> ExternResult<ValidateCallbackResult> {
+    if !SynProperties::get()?.is_store_agent(&action.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only store agents can publish delivery slots".into(),
+        ));
+    }
+    if slot.ends_at <= slot.starts_at {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Delivery slot must end after it starts".into(),
+        ));
+    }
+    if slot.capacity == 0 {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Delivery slot capacity must be positive".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Tag of the `SlotToReservations` links, which point from a slot to the agent reserving it.
+/// Each reservation claims one of the slot's `capacity` places, the oldest link for a place
+/// holds it, so a slot can never have more than `capacity` reservations.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct ReservationTag {
+    pub index: u32,
+    pub cart_id: String,
+}
+
+pub fn validate_create_link_slot_to_reservations(
+    action: &CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let reservation = match ReservationTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed SlotToReservations tag: {e:?}"
+            )))
+        }
+    };
+
+    if AgentPubKey::try_from(target_address).ok().as_ref() != Some(&action.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Agents can only reserve delivery slots for themselves".into(),
+        ));
+    }
+
+    let Some(slot_hash) = base_address.into_action_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "SlotToReservations links must start at a delivery slot".into(),
+        ));
+    };
+    let record = must_get_valid_record(slot_hash)?;
+    let Some(slot) = record
+        .entry()
+        .to_app_option::<DeliverySlot>()
+        .ok()
+        .flatten()
+    else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "SlotToReservations links must start at a delivery slot".into(),
+        ));
+    };
+
+    if reservation.index >= slot.capacity {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Delivery slot only has {} places",
+            slot.capacity
+        )));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_slot_to_reservations(
+    action: &DeleteLink,
+    original_action: &CreateLink,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author != original_action.author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the agent that reserved a delivery slot can cancel the reservation".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
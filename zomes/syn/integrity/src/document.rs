@@ -1,8 +1,18 @@
 use hdi::prelude::*;
 
+use crate::validate_state_chunks;
+
 #[hdk_entry_helper]
 #[derive(Clone)]
 pub struct Document {
     pub initial_state: SerializedBytes,
     pub meta: Option<SerializedBytes>,
+
+    /// Set when `initial_state` was too big for a single entry and got split into `StateChunk`s
+    #[serde(default)]
+    pub state_chunks: Vec<EntryHash>,
+}
+
+pub fn validate_create_document(document: Document) -> ExternResult<ValidateCallbackResult> {
+    validate_state_chunks(&document.initial_state, &document.state_chunks)
 }
@@ -0,0 +1,39 @@
+use hdi::prelude::*;
+
+/// Private reference to the symmetric key that a workspace's session messages are
+/// encrypted with. The newest one is used to encrypt, older ones are kept to decrypt.
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct SessionKey {
+    pub workspace_hash: EntryHash,
+    pub key_ref: XSalsa20Poly1305KeyRef,
+}
+
+/// Tag of the `WorkspaceToEncryptionKey` links, which point from a workspace to the
+/// participant that published the key for that workspace
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct EncryptionKeyTag {
+    pub x25519_pub_key: X25519PubKey,
+}
+
+pub fn validate_create_link_workspace_to_encryption_key(
+    action: &CreateLink,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if EncryptionKeyTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.into_inner())))
+        .is_err()
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Malformed WorkspaceToEncryptionKey tag".into(),
+        ));
+    }
+
+    if AgentPubKey::try_from(target_address).ok().as_ref() != Some(&action.author) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Agents can only publish their own encryption keys".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -0,0 +1,14 @@
+use hdi::prelude::*;
+
+/// Private copy of a received fragment of a session message, kept across zome calls
+/// until the other fragments arrive and deleted once the message is reassembled
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct MessageFragment {
+    pub sender: AgentPubKey,
+    pub message_id: Vec<u8>,
+    pub index: u32,
+    pub total: u32,
+    pub received_at: Timestamp,
+    pub data: SerializedBytes,
+}
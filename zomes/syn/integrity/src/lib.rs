@@ -1,16 +1,32 @@
 use hdi::prelude::*;  // This brings in Serialize, Deserialize, etc.
 use hdi::{hdk_entry_types, hdk_link_types};  // These bring in the attribute macros
 // Add cart to the mod statements at the top
+mod attestation;
+mod chunk;
 mod commit;
 mod document;
+mod encryption;
+mod fragment;
+mod recording;
+mod role;
 mod workspace;
 mod cart;  // New
+mod delivery;
+mod properties;
 
 // Add cart to the pub use statements
+pub use attestation::*;
+pub use chunk::*;
 pub use commit::*;
 pub use document::*;
+pub use encryption::*;
+pub use fragment::*;
+pub use recording::*;
+pub use role::*;
 pub use workspace::*;
 pub use cart::*;  // New
+pub use delivery::*;
+pub use properties::*;
 
 // Add Cart to EntryTypes
 #[derive(Serialize, Deserialize)]
@@ -23,6 +39,17 @@ pub enum EntryTypes {
     Commit(Commit),
     Cart(Cart),  // New
     CloneEntry(CloneEntry),
+    Order(Order),
+    DeliverySlot(DeliverySlot),
+    StateChunk(StateChunk),
+    #[entry_type(visibility = "private")]
+    SessionKey(SessionKey),
+    #[entry_type(visibility = "private")]
+    SessionRecorder(SessionRecorder),
+    #[entry_type(visibility = "private")]
+    RecordedSessionMessage(RecordedSessionMessage),
+    #[entry_type(visibility = "private")]
+    MessageFragment(MessageFragment),
 }
 
 // Add cart-related link types
@@ -33,10 +60,110 @@ pub enum LinkTypes {
     DocumentToAuthors,
     DocumentToWorkspaces,
     DocumentToCommits,
+    CommitToAttestations,
     WorkspaceToTip,
+    WorkspaceToTipResolution,
     WorkspaceToParticipant,
+    WorkspaceToScribe,
+    WorkspaceToEncryptionKey,
+    WorkspaceToForkOrigin,
+    WorkspaceToRole,
+    WorkspaceNameClaim,
     CartToDocument,     // New: Links a cart to its parent document
     CartToParticipant, // New: Links a cart to authorized participants
     CartToSticky,  // Add this
     CartPath,
-}
\ No newline at end of file
+    CartToOrder,
+    DeliverySlots,
+    SlotToReservations,
+}
+
+#[hdk_extern]
+pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
+    match op.flattened::<EntryTypes, LinkTypes>()? {
+        FlatOp::StoreEntry(OpEntry::CreateEntry { app_entry, action }) => match app_entry {
+            EntryTypes::Document(document) => validate_create_document(document),
+            EntryTypes::Commit(commit) => validate_create_commit(commit),
+            EntryTypes::DeliverySlot(slot) => validate_create_delivery_slot(&action, slot),
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::StoreEntry(OpEntry::UpdateEntry { app_entry, .. }) => match app_entry {
+            EntryTypes::DeliverySlot(_) => Ok(ValidateCallbackResult::Invalid(
+                "Delivery slots cannot be updated".into(),
+            )),
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::RegisterCreateLink {
+            link_type,
+            base_address,
+            target_address,
+            tag,
+            action,
+        } => match link_type {
+            LinkTypes::DocumentToWorkspaces => validate_create_link_document_to_workspaces(
+                &action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::WorkspaceNameClaim => {
+                validate_create_link_workspace_name_claim(base_address, target_address, tag)
+            }
+            LinkTypes::WorkspaceToTip => {
+                validate_create_link_workspace_to_tip(base_address, target_address, tag)
+            }
+            LinkTypes::WorkspaceToTipResolution => {
+                validate_create_link_workspace_to_tip_resolution(base_address, target_address, tag)
+            }
+            LinkTypes::WorkspaceToParticipant => {
+                validate_create_link_workspace_to_participant(&action, target_address, tag)
+            }
+            LinkTypes::WorkspaceToScribe => validate_create_link_workspace_to_scribe(
+                &action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::WorkspaceToEncryptionKey => {
+                validate_create_link_workspace_to_encryption_key(&action, target_address, tag)
+            }
+            LinkTypes::CommitToAttestations => {
+                validate_create_link_commit_to_attestations(base_address, target_address, tag)
+            }
+            LinkTypes::WorkspaceToRole => validate_create_link_workspace_to_role(
+                &action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            LinkTypes::SlotToReservations => validate_create_link_slot_to_reservations(
+                &action,
+                base_address,
+                target_address,
+                tag,
+            ),
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        FlatOp::RegisterDeleteLink {
+            link_type,
+            original_action,
+            action,
+            ..
+        } => match link_type {
+            LinkTypes::DocumentToWorkspaces | LinkTypes::WorkspaceNameClaim => {
+                validate_delete_link_document_to_workspaces(&action, &original_action)
+            }
+            LinkTypes::WorkspaceToParticipant => {
+                validate_delete_link_workspace_to_participant(&action, &original_action)
+            }
+            LinkTypes::WorkspaceToRole => {
+                validate_delete_link_workspace_to_role(&action, &original_action)
+            }
+            LinkTypes::SlotToReservations => {
+                validate_delete_link_slot_to_reservations(&action, &original_action)
+            }
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}
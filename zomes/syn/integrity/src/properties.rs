@@ -0,0 +1,38 @@
+use hdi::prelude::holo_hash::AgentPubKeyB64;
+use hdi::prelude::*;
+
+/// Used when the DNA properties don't set a `participant_ttl_secs`
+pub const DEFAULT_PARTICIPANT_TTL_SECS: u64 = 60;
+
+/// DNA properties understood by syn, all of them optional
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, Default)]
+pub struct SynProperties {
+    /// Agents allowed to publish store data like delivery slots, nobody may if empty
+    #[serde(default)]
+    pub store_agents: Vec<AgentPubKeyB64>,
+    /// Number of delta commits after which the next commit should be a full snapshot
+    #[serde(default)]
+    pub snapshot_interval: Option<u32>,
+    /// Seconds without a heartbeat after which a session participant is considered gone
+    #[serde(default)]
+    pub participant_ttl_secs: Option<u64>,
+}
+
+impl SynProperties {
+    pub fn get() -> ExternResult<SynProperties> {
+        // Missing or foreign properties just mean defaults
+        Ok(SynProperties::try_from(dna_info()?.modifiers.properties).unwrap_or_default())
+    }
+
+    pub fn participant_ttl_micros(&self) -> i64 {
+        self.participant_ttl_secs
+            .unwrap_or(DEFAULT_PARTICIPANT_TTL_SECS) as i64
+            * 1_000_000
+    }
+
+    pub fn is_store_agent(&self, agent: &AgentPubKey) -> bool {
+        self.store_agents
+            .iter()
+            .any(|store_agent| AgentPubKey::from(store_agent.clone()).eq(agent))
+    }
+}
@@ -0,0 +1,21 @@
+use hdi::prelude::*;
+
+/// Private toggle for recording a workspace's session messages, the newest one wins
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct SessionRecorder {
+    pub workspace_hash: EntryHash,
+    pub active: bool,
+}
+
+/// Private copy of a session message sent or received while recording was active
+#[hdk_entry_helper]
+#[derive(Clone)]
+pub struct RecordedSessionMessage {
+    pub workspace_hash: EntryHash,
+    /// The sender, ourselves for the messages we sent
+    pub provenance: AgentPubKey,
+    pub recorded_at: Timestamp,
+    /// The `SessionMessage` as it was handled, decrypted and decompressed
+    pub message: SerializedBytes,
+}
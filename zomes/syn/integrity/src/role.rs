@@ -0,0 +1,80 @@
+use hdi::prelude::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceRole {
+    /// Created the workspace, assigns the other roles
+    Owner,
+    Editor,
+    /// Follows the session but its changes are ignored
+    Viewer,
+}
+
+/// Tag of the `WorkspaceToRole` links, which point from a workspace to the agent the role
+/// is assigned to. Workspace entries with the same content share their hash, so the tag
+/// names the create action of the agent that assigned the role. Only the roles naming the
+/// oldest create of the workspace are honored, so later creators can't take it over.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct RoleTag {
+    pub workspace_create_hash: ActionHash,
+    pub role: WorkspaceRole,
+}
+
+pub fn validate_create_link_workspace_to_role(
+    action: &CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let tag = match RoleTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.into_inner())))
+    {
+        Ok(tag) => tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceToRole tag: {e:?}"
+            )))
+        }
+    };
+
+    let Some(workspace_hash) = base_address.into_entry_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToRole links must start at a workspace entry".into(),
+        ));
+    };
+    if target_address.into_agent_pub_key().is_none() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToRole links must point to an agent".into(),
+        ));
+    }
+
+    let workspace_create = must_get_action(tag.workspace_create_hash)?;
+    let Action::Create(create) = workspace_create.action() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The role tag must name the create action of the workspace".into(),
+        ));
+    };
+    if create.entry_hash != workspace_hash {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The role tag names the create action of another entry".into(),
+        ));
+    }
+    if create.author != action.author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the creator of the workspace can assign roles".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_delete_link_workspace_to_role(
+    action: &DeleteLink,
+    original_action: &CreateLink,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author != original_action.author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the agent that assigned a role can revoke it".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
@@ -1,9 +1,475 @@
+use std::collections::HashSet;
+
 use hdi::prelude::*;
 
+use crate::{Commit, LinkTypes, SynProperties};
+
 /// Like a branch in git
 #[hdk_entry_helper]
 #[derive(Clone)]
 pub struct Workspace {
     pub document_hash: AnyDhtHash,
     pub name: String,
+    /// Session messages carrying document state are end-to-end encrypted, see `SessionKey`
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// Tag of the `DocumentToWorkspaces` links. The name lives here as well as in the entry
+/// so that renaming a workspace doesn't change its hash.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct DocumentToWorkspaceTag {
+    pub workspace_name: String,
+    #[serde(default)]
+    pub archived: bool,
+    /// The `WorkspaceNameClaim` link backing the name
+    #[serde(default)]
+    pub name_claim: Option<ActionHash>,
+}
+
+/// Every name of a document's workspaces is claimed with a `WorkspaceNameClaim` link from
+/// this anchor. When several workspaces claim the same name, the oldest claim holds it.
+pub fn workspace_name_anchor(document_hash: &AnyDhtHash, name: &str) -> ExternResult<EntryHash> {
+    Path::from(vec![
+        Component::from("workspace_names"),
+        Component::from(document_hash.to_string()),
+        Component::from(name),
+    ])
+    .path_entry_hash()
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct WorkspaceNameClaimTag {
+    pub document_hash: AnyDhtHash,
+    pub workspace_name: String,
+}
+
+pub fn validate_create_link_workspace_name_claim(
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let tag = match WorkspaceNameClaimTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(tag) => tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceNameClaim tag: {e:?}"
+            )))
+        }
+    };
+
+    if AnyLinkableHash::from(workspace_name_anchor(&tag.document_hash, &tag.workspace_name)?)
+        != base_address
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceNameClaim links must start at the anchor of the claimed name".into(),
+        ));
+    }
+
+    let Some(workspace_hash) = target_address.into_entry_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceNameClaim links must point to a workspace entry".into(),
+        ));
+    };
+    let workspace = match Workspace::try_from(must_get_entry(workspace_hash)?.content) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "WorkspaceNameClaim links must point to a workspace entry: {e:?}"
+            )))
+        }
+    };
+    if workspace.document_hash != tag.document_hash {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Workspace belongs to another document".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub fn validate_create_link_document_to_workspaces(
+    action: &CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let tag = match DocumentToWorkspaceTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(tag) => tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed DocumentToWorkspaces tag: {e:?}"
+            )))
+        }
+    };
+    if tag.workspace_name.trim().is_empty() {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Workspace name cannot be empty".into(),
+        ));
+    }
+
+    let Some(workspace_hash) = target_address.clone().into_entry_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "DocumentToWorkspaces links must point to a workspace entry".into(),
+        ));
+    };
+    let workspace = match Workspace::try_from(must_get_entry(workspace_hash)?.content) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "DocumentToWorkspaces links must point to a workspace entry: {e:?}"
+            )))
+        }
+    };
+    if AnyLinkableHash::from(workspace.document_hash.clone()) != base_address {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Workspace belongs to another document".into(),
+        ));
+    }
+
+    // The name must be claimed by the same agent for the same workspace
+    let Some(claim_hash) = tag.name_claim else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "DocumentToWorkspaces links must name their WorkspaceNameClaim".into(),
+        ));
+    };
+    let Action::CreateLink(claim) = must_get_action(claim_hash)?.action().clone() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The name claim must be a WorkspaceNameClaim link".into(),
+        ));
+    };
+    let claim_type = ScopedLinkType::try_from(LinkTypes::WorkspaceNameClaim)?;
+    if claim.zome_index != claim_type.zome_index || claim.link_type != claim_type.zome_type {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The name claim must be a WorkspaceNameClaim link".into(),
+        ));
+    }
+    let anchor = workspace_name_anchor(&workspace.document_hash, &tag.workspace_name)?;
+    if claim.base_address != AnyLinkableHash::from(anchor)
+        || claim.target_address != target_address
+        || claim.author != action.author
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The name claim is for another name, workspace or agent".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Archiving, renaming and deleting a workspace rewrite its `DocumentToWorkspaces` and
+/// `WorkspaceNameClaim` links, only their author may do that
+pub fn validate_delete_link_document_to_workspaces(
+    action: &DeleteLink,
+    original_action: &CreateLink,
+) -> ExternResult<ValidateCallbackResult> {
+    if action.author != original_action.author {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the agent that linked a workspace to its document can change that link".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Agents can only add themselves to a session
+/// Tag of the `WorkspaceToParticipant` links. Participants renew their presence with a
+/// new link before the previous one expires.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct ParticipantTag {
+    pub expires_at: Timestamp,
+}
+
+impl ParticipantTag {
+    /// Links created before participant tags existed last a TTL from their creation
+    pub fn expires_at(link_timestamp: Timestamp, tag: LinkTag, ttl_micros: i64) -> Timestamp {
+        match ParticipantTag::try_from(SerializedBytes::from(UnsafeBytes::from(tag.into_inner())))
+        {
+            Ok(tag) => tag.expires_at,
+            Err(_) => Timestamp::from_micros(link_timestamp.as_micros() + ttl_micros),
+        }
+    }
+}
+
+pub fn validate_create_link_workspace_to_participant(
+    action: &CreateLink,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    if target_address.into_agent_pub_key() != Some(action.author.clone()) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Agents can only add themselves as session participants".into(),
+        ));
+    }
+
+    let participant_tag = match ParticipantTag::try_from(SerializedBytes::from(
+        UnsafeBytes::from(tag.into_inner()),
+    )) {
+        Ok(participant_tag) => participant_tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceToParticipant tag: {e:?}"
+            )))
+        }
+    };
+    let ttl = SynProperties::get()?.participant_ttl_micros();
+    let expires_in = participant_tag.expires_at.as_micros() - action.timestamp.as_micros();
+    if expires_in <= 0 || expires_in > ttl {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Participant links must expire within the participant TTL".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Participants remove themselves when leaving, anybody can clean up expired links
+pub fn validate_delete_link_workspace_to_participant(
+    action: &DeleteLink,
+    original_action: &CreateLink,
+) -> ExternResult<ValidateCallbackResult> {
+    let expires_at = ParticipantTag::expires_at(
+        original_action.timestamp,
+        original_action.tag.clone(),
+        SynProperties::get()?.participant_ttl_micros(),
+    );
+    if action.author != original_action.author && action.timestamp <= expires_at {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the participant can remove itself from a session before its link expires".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Tag of the `WorkspaceToTip` links: the commits the new tip replaces as tips
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct PreviousCommitsTag(pub Vec<ActionHash>);
+
+/// Workspace roles are not checked here: the `WorkspaceToRole` links can't be read during
+/// validation, so a viewer can still create tips. Keeping viewers' changes out of the session
+/// is up to the coordinator zome and the other participants.
+pub fn validate_create_link_workspace_to_tip(
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let previous_commit_hashes = match PreviousCommitsTag::try_from(SerializedBytes::from(
+        UnsafeBytes::from(tag.into_inner()),
+    )) {
+        Ok(tag) => tag.0,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceToTip tag: {e:?}"
+            )))
+        }
+    };
+
+    let Some(workspace_hash) = base_address.into_entry_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToTip links must start at a workspace entry".into(),
+        ));
+    };
+    let workspace = match Workspace::try_from(must_get_entry(workspace_hash)?.content) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "WorkspaceToTip links must start at a workspace entry: {e:?}"
+            )))
+        }
+    };
+
+    let Some(tip_hash) = target_address.into_action_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToTip links must point to a commit".into(),
+        ));
+    };
+    let Some(tip) = must_get_commit(tip_hash.clone())? else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToTip links must point to a commit".into(),
+        ));
+    };
+    if tip.document_hash != workspace.document_hash {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The new tip is a commit of another document".into(),
+        ));
+    }
+
+    // Every previous commit listed in the tag must be a parent of the new tip, otherwise a link
+    // could hide unrelated tips. Checking ancestry further back would make validation cost grow
+    // with the history, and tips only ever move to commits built directly on top of them.
+    if previous_commit_hashes
+        .iter()
+        .any(|previous| !tip.previous_commit_hashes.contains(previous))
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Previous commits in the tag must be previous commits of the new tip".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Tag of the `WorkspaceToTipResolution` links, which point from a workspace to the tip that
+/// supersedes the `losers`. `tip_links` are the `WorkspaceToTip` links of the winner and the
+/// losers, proving that they all were tips of the workspace.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+pub struct TipResolutionTag {
+    pub losers: Vec<ActionHash>,
+    pub tip_links: Vec<ActionHash>,
+}
+
+pub fn validate_create_link_workspace_to_tip_resolution(
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let resolution = match TipResolutionTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(resolution) => resolution,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceToTipResolution tag: {e:?}"
+            )))
+        }
+    };
+
+    let Some(winner) = target_address.into_action_hash() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "WorkspaceToTipResolution links must point to a commit".into(),
+        ));
+    };
+
+    // Valid tip links only point to commits of the workspace's document, see
+    // `validate_create_link_workspace_to_tip`
+    let tip_type = ScopedLinkType::try_from(LinkTypes::WorkspaceToTip)?;
+    let mut tips: HashSet<ActionHash> = HashSet::new();
+    for tip_link_hash in resolution.tip_links {
+        let record = must_get_valid_record(tip_link_hash)?;
+        let Action::CreateLink(tip_link) = record.action() else {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Tip links of a resolution must be WorkspaceToTip links".into(),
+            ));
+        };
+        if tip_link.zome_index != tip_type.zome_index
+            || tip_link.link_type != tip_type.zome_type
+            || tip_link.base_address != base_address
+        {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Tip links of a resolution must be WorkspaceToTip links of the workspace".into(),
+            ));
+        }
+        if let Some(tip) = tip_link.target_address.clone().into_action_hash() {
+            tips.insert(tip);
+        }
+    }
+
+    if !tips.contains(&winner) || resolution.losers.iter().any(|loser| !tips.contains(loser)) {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The winner and the losers of a resolution must be tips of the workspace".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+pub(crate) fn must_get_commit(commit_hash: ActionHash) -> ExternResult<Option<Commit>> {
+    let record = must_get_valid_record(commit_hash)?;
+
+    Ok(record.entry().to_app_option::<Commit>().ok().flatten())
+}
+
+/// Tag of the `WorkspaceToScribe` links. Every appointment bumps the term of the
+/// `previous` appointment, the link with the highest term designates the current scribe.
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct ScribeTag {
+    pub term: u64,
+    pub previous: Option<ActionHash>,
+}
+
+/// Agents can only appoint themselves, or hand the role over if they held the previous term
+pub fn validate_create_link_workspace_to_scribe(
+    action: &CreateLink,
+    base_address: AnyLinkableHash,
+    target_address: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let scribe_tag = match ScribeTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        tag.into_inner(),
+    ))) {
+        Ok(scribe_tag) => scribe_tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed WorkspaceToScribe tag: {e:?}"
+            )))
+        }
+    };
+
+    let self_appointed = target_address
+        .into_agent_pub_key()
+        .is_some_and(|scribe| scribe.eq(&action.author));
+
+    let Some(previous_hash) = scribe_tag.previous else {
+        if scribe_tag.term != 0 {
+            return Ok(ValidateCallbackResult::Invalid(
+                "The first scribe appointment must have term 0".into(),
+            ));
+        }
+        if !self_appointed {
+            return Ok(ValidateCallbackResult::Invalid(
+                "Agents can only appoint themselves as the first scribe".into(),
+            ));
+        }
+        return Ok(ValidateCallbackResult::Valid);
+    };
+
+    let record = must_get_valid_record(previous_hash)?;
+    let scribe_type = ScopedLinkType::try_from(LinkTypes::WorkspaceToScribe)?;
+    let Action::CreateLink(previous) = record.action() else {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The previous scribe appointment must be a WorkspaceToScribe link".into(),
+        ));
+    };
+    if previous.zome_index != scribe_type.zome_index
+        || previous.link_type != scribe_type.zome_type
+        || previous.base_address != base_address
+    {
+        return Ok(ValidateCallbackResult::Invalid(
+            "The previous scribe appointment must be a WorkspaceToScribe link of the workspace"
+                .into(),
+        ));
+    }
+    let previous_tag = match ScribeTag::try_from(SerializedBytes::from(UnsafeBytes::from(
+        previous.tag.clone().into_inner(),
+    ))) {
+        Ok(previous_tag) => previous_tag,
+        Err(e) => {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "Malformed previous WorkspaceToScribe tag: {e:?}"
+            )))
+        }
+    };
+    if scribe_tag.term != previous_tag.term + 1 {
+        return Ok(ValidateCallbackResult::Invalid(
+            "A scribe appointment must bump the term of the previous one by one".into(),
+        ));
+    }
+
+    let handed_over = previous
+        .target_address
+        .clone()
+        .into_agent_pub_key()
+        .is_some_and(|previous_scribe| previous_scribe.eq(&action.author));
+    if !self_appointed && !handed_over {
+        return Ok(ValidateCallbackResult::Invalid(
+            "Only the previous scribe can appoint somebody else".into(),
+        ));
+    }
+
+    Ok(ValidateCallbackResult::Valid)
 }